@@ -0,0 +1,75 @@
+use anchor_lang::prelude::*;
+use solignition::cpi::accounts::RepayFromProgram;
+use solignition::program::Solignition;
+use solignition::REPAYER_SEED;
+
+declare_id!("4y6cbxDDzuHzmDyu4FL3zyqSpFCZ4hMM8t84ccNsebmk");
+
+/// Example of a financed program paying down its own Solignition loan.
+///
+/// Revenue is collected in the program's repayer PDA (`[REPAYER_SEED]` under
+/// this program's id). `repay` forwards it to the lending protocol through
+/// CPI, signing for the PDA. Once this program is recorded as the loan's
+/// `program_pubkey`, anyone can crank it. If the loan's position has been
+/// sold, pass the loan's proceeds escrow as a remaining account; it is
+/// forwarded unchanged.
+#[program]
+pub mod auto_repay {
+    use super::*;
+
+    /// Pay `amount` lamports from the repayer PDA towards the loan
+    pub fn repay<'info>(ctx: Context<'_, '_, '_, 'info, Repay<'info>>, loan_id: u64, amount: u64) -> Result<()> {
+        let repayer_seeds: &[&[u8]] = &[REPAYER_SEED, &[ctx.bumps.repayer]];
+        let signer = &[repayer_seeds];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.solignition_program.to_account_info(),
+            RepayFromProgram {
+                repayer: ctx.accounts.repayer.to_account_info(),
+                loan: ctx.accounts.loan.to_account_info(),
+                protocol_config: ctx.accounts.protocol_config.to_account_info(),
+                vault: ctx.accounts.vault.to_account_info(),
+                treasury: ctx.accounts.treasury.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                event_authority: ctx.accounts.event_authority.to_account_info(),
+                program: ctx.accounts.solignition_program.to_account_info(),
+            },
+            signer,
+        )
+        .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        solignition::cpi::repay_from_program(cpi_ctx, loan_id, amount)
+    }
+}
+
+#[derive(Accounts)]
+pub struct Repay<'info> {
+    /// Holds the revenue set aside for repayment
+    #[account(
+        mut,
+        seeds = [REPAYER_SEED],
+        bump
+    )]
+    pub repayer: SystemAccount<'info>,
+
+    /// CHECK: Loan account, validated by solignition
+    #[account(mut)]
+    pub loan: UncheckedAccount<'info>,
+
+    /// CHECK: Pool config, validated by solignition
+    #[account(mut)]
+    pub protocol_config: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault, validated by solignition
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: Pool treasury, validated by solignition
+    #[account(mut)]
+    pub treasury: UncheckedAccount<'info>,
+
+    /// CHECK: Event authority of solignition, validated by solignition
+    pub event_authority: UncheckedAccount<'info>,
+
+    pub solignition_program: Program<'info, Solignition>,
+    pub system_program: Program<'info, System>,
+}
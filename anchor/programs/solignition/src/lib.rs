@@ -1,15 +1,22 @@
+// The generated CPI wrappers for `update_config` and friends take as many
+// arguments as the instructions themselves
+#![allow(clippy::too_many_arguments)]
+
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{
-    bpf_loader_upgradeable,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    ed25519_program,
+    hash::hashv,
     program::{invoke, invoke_signed},
     system_instruction,
+    sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
 };
 use anchor_spl::{
     metadata::{
                create_metadata_accounts_v3, mpl_token_metadata::types::DataV2,
                CreateMetadataAccountsV3, Metadata,
              },
-              token::{self, Mint, Token, TokenAccount, MintTo, Transfer},
+              token::{self, spl_token::instruction::AuthorityType, CloseAccount, Mint, Token, TokenAccount, MintTo, SetAuthority, Transfer},
               associated_token::{AssociatedToken}
 };
 
@@ -23,7 +30,19 @@ pub const LOAN_SEED: &[u8] = b"loan";
 pub const DEPOSITOR_SEED: &[u8] = b"depositor";
 pub const DEPLOYER_SEED: &[u8] = b"deployer";
 pub const PROTOCOL_CONFIG_SEED: &[u8] = b"config";
+pub const SENIOR_SEED: &[u8] = b"senior";
+pub const JUNIOR_SEED: &[u8] = b"junior";
+pub const QUOTE_NONCE_SEED: &[u8] = b"quote_nonce";
+pub const REPAYER_SEED: &[u8] = b"repayer"; // Derived under the financed program's id
+pub const AUCTION_SEED: &[u8] = b"auction";
+pub const POSITION_SEED: &[u8] = b"position";
+pub const COLLATERAL_SEED: &[u8] = b"collateral";
+pub const COLLATERAL_CONFIG_SEED: &[u8] = b"collateral_config";
+pub const GUARANTEE_SEED: &[u8] = b"guarantee";
+pub const PROCEEDS_SEED: &[u8] = b"proceeds";
 pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+pub const DEFAULT_APPROVAL_TIMEOUT: i64 = 259_200; // 3 days
+pub const MAX_ACTIVE_DEPLOYERS: usize = 8;
 
 /// Solana Developer Lending Protocol
 /// 
@@ -31,39 +50,88 @@ pub const SECONDS_PER_YEAR: u64 = 31_536_000;
 /// - Trustless upgrade authority management
 /// - Automated loan recovery and repayment
 /// - Fair yield distribution to depositors
+/// - Senior/junior tranches, with juniors absorbing recovery losses first
+/// - Independent pools, each with its own config, vault and depositors
 /// - Secure fee collection and distribution
+///
+/// Every PDA except the pool's `ProtocolConfig` is seeded with the config's
+/// pubkey, so one deployment can run several pools side by side.
 /// 
 /// Recovery Flow for Expired Loans:
 /// 1. Call `recover_loan` when loan expires to mark it recovered
-/// 2. Off-chain deployer can close the program account
-/// 3. Call `return_reclaimed_sol` to return recovered SOL to vault
+/// 2. During the redemption window the borrower can buy the program back
+///    with `redeem_loan`
+/// 3. Optionally, `start_authority_auction` sells the program's upgrade
+///    authority in a Dutch auction, with proceeds covering the bad debt
+/// 4. Call `close_and_reclaim` to close the program on-chain, with its rent
+///    going straight to the vault, once any auction has ended unsold
+///
+/// A financed program's upgrade authority is held by the pool's authority PDA
+/// from `set_deployed_program` until `transfer_authority_to_borrower` hands
+/// it to the borrower after repayment. SOL a deployer still holds for a loan
+/// that was never deployed comes back with `return_reclaimed_sol`.
 
 #[program]
 pub mod solignition {
     use super::*;
 
     /// Initialize the protocol with admin and configuration
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize(
         ctx: Context<Initialize>,
+        pool_id: u64,
         admin_fee_split_bps: u16,  // % of admin fee to depositors vs treasury
         default_interest_rate_bps: u16,
         default_admin_fee_bps: u16,
+        senior_draw_bps: u16,        // % of each loan drawn from the senior tranche
+        junior_yield_share_bps: u16, // % of depositor yield credited to the junior tranche
     ) -> Result<()> {
+        require!(senior_draw_bps <= 10000, ErrorCode::InvalidParameter);
+        require!(junior_yield_share_bps <= 10000, ErrorCode::InvalidParameter);
+
         let config = &mut ctx.accounts.protocol_config;
+        config.pool_id = pool_id;
         config.admin = ctx.accounts.admin.key();
         config.treasury = ctx.accounts.treasury.key();
-        config.deployer = ctx.accounts.deployer.key();
+        config.active_deployers = vec![ctx.accounts.deployer.key()];
+        config.next_deployer = 0;
         config.bump = ctx.bumps.protocol_config;
         config.admin_fee_split_bps = admin_fee_split_bps;
         config.default_interest_rate_bps = default_interest_rate_bps;
         config.default_admin_fee_bps = default_admin_fee_bps;
+        config.senior_draw_bps = senior_draw_bps;
+        config.junior_yield_share_bps = junior_yield_share_bps;
+        config.senior = TrancheState::default();
+        config.junior = TrancheState::default();
+        config.underwriter = ctx.accounts.admin.key();
+        config.auto_approve_threshold = u64::MAX; // Everything auto-approved until configured
+        config.approval_timeout = DEFAULT_APPROVAL_TIMEOUT;
+        config.quote_signer = Pubkey::default(); // Signed quotes disabled until configured
+        config.borrower_allowlist_root = None;
+        config.extension_fee_bps = 0;
+        config.grace_period = 0;
+        config.penalty_interest_rate_bps = 0; // Overdue loans keep their own rate until configured
+        config.min_interest_period = 0;
+        config.min_interest_lamports = 0;
+        config.keeper_bounty_bps = 0;
+        config.redemption_window = 0;
+        config.recovery_penalty_bps = 0;
         config.total_deposits = 0;
         config.total_loans_outstanding = 0;
         config.is_paused = false;
         config.loan_counter = 0;
-        
+
+        // The initial deployer is registered and active
+        let deployer_record = &mut ctx.accounts.deployer_record;
+        deployer_record.pool = ctx.accounts.protocol_config.key();
+        deployer_record.deployer = ctx.accounts.deployer.key();
+        deployer_record.active = true;
+        deployer_record.registered_ts = Clock::get()?.unix_timestamp;
+        deployer_record.bump = ctx.bumps.deployer_record;
         
         emit_cpi!(ProtocolInitialized {
+            pool: ctx.accounts.protocol_config.key(),
+            pool_id,
             admin: ctx.accounts.admin.key(),
             treasury: ctx.accounts.treasury.key(),
         });
@@ -71,11 +139,18 @@ pub mod solignition {
         Ok(())
     }
 
-    /// Deposit SOL into the vault
-    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
+    /// Deposit SOL into the vault on behalf of a tranche
+    pub fn deposit(ctx: Context<Deposit>, tranche: Tranche, amount: u64) -> Result<()> {
         require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
         require!(amount > 0, ErrorCode::InvalidAmount);
 
+        // Price the deposit against the tranche's current value per share
+        let shares = ctx.accounts.protocol_config
+            .tranche(tranche)
+            .shares_for_deposit(amount)
+            .ok_or(ErrorCode::TrancheWipedOut)?;
+        require!(shares > 0, ErrorCode::InvalidAmount);
+
         // Transfer SOL from depositor to vault
         let ix = system_instruction::transfer(
             &ctx.accounts.depositor.key(),
@@ -94,37 +169,53 @@ pub mod solignition {
         // Update or create depositor record
         let depositor_record = &mut ctx.accounts.depositor_record;
         depositor_record.owner = ctx.accounts.depositor.key();
+        depositor_record.pool = ctx.accounts.protocol_config.key();
+        depositor_record.tranche = tranche;
         depositor_record.deposited_amount += amount;
-        depositor_record.share_amount += amount; // 1:1 initially, can be adjusted for yield
+        depositor_record.share_amount += shares;
         depositor_record.last_update_ts = Clock::get()?.unix_timestamp;
         depositor_record.bump = ctx.bumps.depositor_record;
 
-        // Update protocol totals
-        ctx.accounts.protocol_config.total_deposits += amount;
+        // Update tranche and protocol totals
+        let config = &mut ctx.accounts.protocol_config;
+        let tranche_state = config.tranche_mut(tranche);
+        tranche_state.total_deposits += amount;
+        tranche_state.total_shares += shares;
+        config.total_deposits += amount;
 
         emit_cpi!(Deposited {
+            pool: config.key(),
             depositor: ctx.accounts.depositor.key(),
+            tranche,
             amount,
-            total_deposits: ctx.accounts.protocol_config.total_deposits,
+            shares,
+            total_deposits: config.total_deposits,
         });
 
         Ok(())
     }
 
-    /// Withdraw SOL from the vault
-    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+    /// Redeem tranche shares for SOL from the vault
+    pub fn withdraw(ctx: Context<Withdraw>, tranche: Tranche, shares: u64, min_lamports_out: u64) -> Result<()> {
         require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+        require!(shares > 0, ErrorCode::InvalidAmount);
 
         let depositor_record = &ctx.accounts.depositor_record;
-        require!(amount <= depositor_record.share_amount, ErrorCode::InsufficientBalance);
-        
-        // Calculate available liquidity (total deposits - outstanding loans)
+        require!(shares <= depositor_record.share_amount, ErrorCode::InsufficientBalance);
+
+        let tranche_state = ctx.accounts.protocol_config.tranche(tranche);
+        let amount = tranche_state.lamports_for_shares(shares);
+        require!(amount >= min_lamports_out, ErrorCode::SlippageExceeded);
+
+        // The tranche must have its own liquidity, and the shared vault must too
+        require!(amount <= tranche_state.available_liquidity(), ErrorCode::InsufficientLiquidity);
         let available = ctx.accounts.protocol_config.total_deposits
             .saturating_sub(ctx.accounts.protocol_config.total_loans_outstanding);
         require!(amount <= available, ErrorCode::InsufficientLiquidity);
 
         // Transfer SOL from vault to depositor
-        let vault_seeds = &[VAULT_SEED, &[ctx.bumps.vault]];
+        let pool = ctx.accounts.protocol_config.key();
+        let vault_seeds = &[VAULT_SEED, pool.as_ref(), &[ctx.bumps.vault]];
         let signer = &[&vault_seeds[..]];
 
         let ix = system_instruction::transfer(
@@ -146,16 +237,23 @@ pub mod solignition {
 
         // Update depositor record
         let depositor_record = &mut ctx.accounts.depositor_record;
-        depositor_record.share_amount = depositor_record.share_amount.saturating_sub(amount);
+        depositor_record.share_amount = depositor_record.share_amount.saturating_sub(shares);
         depositor_record.deposited_amount = depositor_record.deposited_amount.saturating_sub(amount);
         depositor_record.last_update_ts = Clock::get()?.unix_timestamp;
 
-        // Update protocol totals
-        ctx.accounts.protocol_config.total_deposits -= amount;
+        // Update tranche and protocol totals
+        let config = &mut ctx.accounts.protocol_config;
+        let tranche_state = config.tranche_mut(tranche);
+        tranche_state.total_deposits -= amount;
+        tranche_state.total_shares -= shares;
+        config.total_deposits -= amount;
 
         emit_cpi!(Withdrawn {
+            pool: config.key(),
             depositor: ctx.accounts.depositor.key(),
+            tranche,
             amount,
+            shares,
             remaining_balance: depositor_record.share_amount,
         });
 
@@ -163,12 +261,34 @@ pub mod solignition {
     }
 
     /// Request a loan and pay upfront admin fee
+    ///
+    /// With a `quote`, the terms must match a quote signed by the pool's quote
+    /// signer. The signature is checked through an ed25519 program instruction
+    /// earlier in the same transaction. Quoted loans skip the default rate and
+    /// fee floors and are funded without underwriter review.
+    ///
+    /// Pools with a borrower allowlist require a Merkle proof for the borrower.
+    ///
+    /// Passing a whitelisted mint's collateral config makes the loan
+    /// collateralised: the collateral the mint's ratio requires is moved into
+    /// a token account escrowed for this loan.
+    ///
+    /// A `guarantee_lamports` above zero needs a guarantor co-signing the
+    /// request. They fund an escrow for this loan that covers a shortfall on
+    /// recovery before depositors take a loss.
+    ///
+    /// The loan is assigned to the pool's active deployers in turn; the
+    /// client passes the one `ProtocolConfig::assigned_deployer` names.
+    #[allow(clippy::too_many_arguments)]
     pub fn request_loan(
         ctx: Context<RequestLoan>,
         principal: u64,
         duration: i64,
         interest_rate_bps: u16,
         admin_fee_bps: u16,
+        quote: Option<LoanQuote>,
+        allowlist_proof: Vec<[u8; 32]>,
+        guarantee_lamports: u64,
     ) -> Result<()> {
         require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
         require!(principal > 0, ErrorCode::InvalidAmount);
@@ -176,6 +296,54 @@ pub mod solignition {
         require!(interest_rate_bps <= 10000, ErrorCode::InvalidInterestRate);
         require!(admin_fee_bps <= 10000, ErrorCode::InvalidAdminFee);
 
+        if let Some(root) = ctx.accounts.protocol_config.borrower_allowlist_root {
+            require!(
+                verify_merkle_proof(&allowlist_proof, &root, &ctx.accounts.borrower.key()),
+                ErrorCode::BorrowerNotAllowlisted
+            );
+        }
+
+        let clock = Clock::get()?;
+        if let Some(quote) = &quote {
+            let config = &ctx.accounts.protocol_config;
+            require!(config.quote_signer != Pubkey::default(), ErrorCode::QuotesDisabled);
+            require!(quote.pool == config.key(), ErrorCode::PoolMismatch);
+            require!(quote.borrower == ctx.accounts.borrower.key(), ErrorCode::UnauthorizedBorrower);
+            require!(clock.unix_timestamp < quote.expiry, ErrorCode::QuoteExpired);
+            require!(
+                quote.principal == principal &&
+                quote.duration == duration &&
+                quote.interest_rate_bps == interest_rate_bps &&
+                quote.admin_fee_bps == admin_fee_bps,
+                ErrorCode::QuoteTermsMismatch
+            );
+
+            let instructions = ctx.accounts.instructions
+                .as_ref()
+                .ok_or(ErrorCode::MissingQuoteSignature)?;
+            verify_quote_signature(instructions, &config.quote_signer, &quote.try_to_vec()?)?;
+
+            // Creating the nonce account fails if this quote was already used
+            let quote_nonce = ctx.accounts.quote_nonce
+                .as_mut()
+                .ok_or(ErrorCode::MissingQuoteNonce)?;
+            quote_nonce.pool = quote.pool;
+            quote_nonce.borrower = quote.borrower;
+            quote_nonce.nonce = quote.nonce;
+            quote_nonce.used_ts = clock.unix_timestamp;
+            quote_nonce.bump = ctx.bumps.quote_nonce.ok_or(ErrorCode::MissingQuoteNonce)?;
+        } else {
+            // Default policy: borrowers choosing their own terms pay at least the pool defaults
+            require!(
+                interest_rate_bps >= ctx.accounts.protocol_config.default_interest_rate_bps,
+                ErrorCode::InvalidInterestRate
+            );
+            require!(
+                admin_fee_bps >= ctx.accounts.protocol_config.default_admin_fee_bps,
+                ErrorCode::InvalidAdminFee
+            );
+        }
+
         //require!(ctx.accounts.protocol_config.loan_counter == , ErrorCode::InvalidLoanCounter);
 
         // Calculate upfront admin fee
@@ -190,7 +358,7 @@ pub mod solignition {
             .saturating_sub(ctx.accounts.protocol_config.total_loans_outstanding);
         require!(principal <= available, ErrorCode::InsufficientLiquidity);
 
-        // Pay admin fee directly to admin PDA
+        // Escrow admin fee in the admin PDA until the loan is funded or refunded
         if admin_fee > 0 {
             let ix = system_instruction::transfer(
                 &ctx.accounts.borrower.key(),
@@ -207,63 +375,258 @@ pub mod solignition {
             )?;
         }
 
-        // Transfer principal from vault to deployer
-        // The deployer will handle program deployment off-chain
-        // Any unused or reclaimed SOL can be returned via return_reclaimed_sol
-        let vault_seeds = &[VAULT_SEED, &[ctx.bumps.vault]];
-        let signer = &[&vault_seeds[..]];
-
-        let ix = system_instruction::transfer(
-        &ctx.accounts.vault.key(),
-        &ctx.accounts.deployer.key(),
-        principal,
-         );
-        invoke_signed(
-            &ix,
-            &[
-                ctx.accounts.vault.to_account_info(),
-                ctx.accounts.deployer.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-            signer,
-        )?;
-        
         // Create loan record
         let loan = &mut ctx.accounts.loan;
         loan.loan_id = ctx.accounts.protocol_config.loan_counter;
+        loan.pool = ctx.accounts.protocol_config.key();
         loan.borrower = ctx.accounts.borrower.key();
+        loan.deployer = ctx.accounts.deployer.key();
         loan.program_pubkey = Pubkey::default(); // Will be set after deployment
         loan.principal = principal;
+        loan.principal_outstanding = 0;
+        loan.senior_principal = 0;
+        loan.junior_principal = 0;
+        loan.senior_loss = 0;
         loan.duration = duration;
         loan.interest_rate_bps = interest_rate_bps;
         loan.admin_fee_bps = admin_fee_bps;
         loan.admin_fee_paid = admin_fee;
-        loan.start_ts = Clock::get()?.unix_timestamp;
-        loan.state = LoanState::Pending;
+        loan.extension_count = 0;
+        loan.requested_ts = clock.unix_timestamp;
+        loan.start_ts = clock.unix_timestamp;
+        loan.last_accrual_ts = clock.unix_timestamp;
+        loan.state = LoanState::AwaitingApproval;
         loan.repaid_ts = Some(0);
         loan.recovered_ts = Some(0);
         loan.interest_paid = Some(0);
         loan.reclaimed_amount = Some(0);
         loan.reclaimed_ts = Some(0);
+        loan.deployment_cost = None;
+        loan.position_mint = None;
+        loan.position_sold = false;
+        loan.collateral_mint = None;
+        loan.collateral_amount = 0;
+        loan.collateral_owner = Pubkey::default();
+        loan.guarantor = None;
+        loan.guarantee_amount = 0;
+        loan.guarantee_claimed = 0;
+        loan.guarantee_refund_due = 0;
         loan.bump = ctx.bumps.loan;
 
+        if let Some(collateral_config) = &ctx.accounts.collateral_config {
+            require!(collateral_config.enabled, ErrorCode::CollateralMintNotAllowed);
+            let (Some(mint), Some(from), Some(to), Some(token_program)) = (
+                &ctx.accounts.collateral_mint,
+                &ctx.accounts.borrower_collateral,
+                &ctx.accounts.loan_collateral,
+                &ctx.accounts.token_program,
+            ) else {
+                return err!(ErrorCode::MissingCollateralAccounts);
+            };
+            require!(mint.key() == collateral_config.mint, ErrorCode::InvalidCollateralAccount);
+
+            let amount = required_collateral(
+                principal,
+                collateral_config.collateral_ratio_bps,
+                collateral_config.lamports_per_token,
+                mint.decimals,
+            ).ok_or(ErrorCode::InvalidAmount)?;
+
+            token::transfer(
+                CpiContext::new(
+                    token_program.to_account_info(),
+                    Transfer {
+                        from: from.to_account_info(),
+                        to: to.to_account_info(),
+                        authority: ctx.accounts.borrower.to_account_info(),
+                    },
+                ),
+                amount,
+            )?;
+
+            loan.collateral_mint = Some(mint.key());
+            loan.collateral_amount = amount;
+            loan.collateral_owner = ctx.accounts.borrower.key();
+        }
+
+        if guarantee_lamports > 0 {
+            let (Some(guarantor), Some(guarantee_escrow)) = (
+                &ctx.accounts.guarantor,
+                &ctx.accounts.guarantee_escrow,
+            ) else {
+                return err!(ErrorCode::MissingGuarantor);
+            };
+            // The escrow holds no data, so it must stay rent exempt on its own
+            require!(
+                guarantee_lamports >= Rent::get()?.minimum_balance(0),
+                ErrorCode::InvalidAmount
+            );
+
+            let ix = system_instruction::transfer(
+                &guarantor.key(),
+                &guarantee_escrow.key(),
+                guarantee_lamports,
+            );
+            invoke(
+                &ix,
+                &[
+                    guarantor.to_account_info(),
+                    guarantee_escrow.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+
+            loan.guarantor = Some(guarantor.key());
+            loan.guarantee_amount = guarantee_lamports;
+        }
+
+        // Small and quoted loans are funded straight away; larger ones wait for an underwriter
+        let auto_approved = quote.is_some() ||
+            principal <= ctx.accounts.protocol_config.auto_approve_threshold;
+        if auto_approved {
+            disburse_principal(
+                &mut ctx.accounts.protocol_config,
+                loan,
+                &ctx.accounts.vault,
+                &ctx.accounts.deployer,
+                &mut ctx.accounts.deployer_record,
+                &ctx.accounts.system_program,
+                ctx.bumps.vault,
+            )?;
+        }
+
         // Update protocol state
-        ctx.accounts.protocol_config.total_loans_outstanding += principal;
-        ctx.accounts.protocol_config.loan_counter += 1;
+        let config = &mut ctx.accounts.protocol_config;
+        config.loan_counter += 1;
+        config.advance_deployer();
 
         emit_cpi!(LoanRequested {
+            pool: config.key(),
             borrower: ctx.accounts.borrower.key(),
+            deployer: loan.deployer,
             loan_id: loan.loan_id,
             principal,
+            senior_principal: loan.senior_principal,
+            junior_principal: loan.junior_principal,
             duration,
             interest_rate_bps,
             admin_fee,
+            awaiting_approval: !auto_approved,
+            quote_nonce: quote.map(|q| q.nonce),
+            collateral_mint: loan.collateral_mint,
+            collateral_amount: loan.collateral_amount,
+            guarantor: loan.guarantor,
+            guarantee_amount: loan.guarantee_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Underwriter releases principal for a loan request awaiting review
+    pub fn approve_loan(ctx: Context<ApproveLoan>, loan_id: u64) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(loan.state == LoanState::AwaitingApproval, ErrorCode::LoanNotAwaitingApproval);
+
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp < loan.requested_ts + ctx.accounts.protocol_config.approval_timeout,
+            ErrorCode::LoanRequestExpired
+        );
+
+        disburse_principal(
+            &mut ctx.accounts.protocol_config,
+            &mut ctx.accounts.loan,
+            &ctx.accounts.vault,
+            &ctx.accounts.deployer,
+            &mut ctx.accounts.deployer_record,
+            &ctx.accounts.system_program,
+            ctx.bumps.vault,
+        )?;
+
+        let loan = &ctx.accounts.loan;
+        emit_cpi!(LoanApproved {
+            pool: ctx.accounts.protocol_config.key(),
+            loan_id,
+            underwriter: ctx.accounts.underwriter.key(),
+            senior_principal: loan.senior_principal,
+            junior_principal: loan.junior_principal,
+        });
+
+        Ok(())
+    }
+
+    /// Underwriter declines a loan request and refunds the escrowed admin fee
+    pub fn reject_loan(ctx: Context<RejectLoan>, loan_id: u64) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(loan.state == LoanState::AwaitingApproval, ErrorCode::LoanNotAwaitingApproval);
+
+        let refunded_fee = loan.admin_fee_paid;
+        refund_escrowed_fee(
+            &ctx.accounts.protocol_config,
+            &ctx.accounts.admin_pda,
+            &ctx.accounts.borrower,
+            &ctx.accounts.system_program,
+            ctx.bumps.admin_pda,
+            refunded_fee,
+        )?;
+
+        let loan = &mut ctx.accounts.loan;
+        loan.state = LoanState::Rejected;
+        loan.admin_fee_paid = 0;
+
+        emit_cpi!(LoanRejected {
+            pool: ctx.accounts.protocol_config.key(),
+            loan_id,
+            underwriter: ctx.accounts.underwriter.key(),
+            refunded_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Expire a loan request the underwriter did not review in time.
+    /// Anyone may call this; the escrowed admin fee goes back to the borrower.
+    pub fn expire_loan_request(ctx: Context<ExpireLoanRequest>, loan_id: u64) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(loan.state == LoanState::AwaitingApproval, ErrorCode::LoanNotAwaitingApproval);
+
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp >= loan.requested_ts + ctx.accounts.protocol_config.approval_timeout,
+            ErrorCode::LoanRequestNotExpired
+        );
+
+        let refunded_fee = loan.admin_fee_paid;
+        refund_escrowed_fee(
+            &ctx.accounts.protocol_config,
+            &ctx.accounts.admin_pda,
+            &ctx.accounts.borrower,
+            &ctx.accounts.system_program,
+            ctx.bumps.admin_pda,
+            refunded_fee,
+        )?;
+
+        let loan = &mut ctx.accounts.loan;
+        loan.state = LoanState::Expired;
+        loan.admin_fee_paid = 0;
+
+        emit_cpi!(LoanRequestExpired {
+            pool: ctx.accounts.protocol_config.key(),
+            loan_id,
+            refunded_fee,
         });
 
         Ok(())
     }
 
-    /// Set the deployed program pubkey after off-chain deployment
+    /// Set the deployed program pubkey after off-chain deployment. The
+    /// deployer must already have handed the program's upgrade authority to
+    /// the pool's authority PDA.
     pub fn set_deployed_program(
         ctx: Context<SetDeployedProgram>,
         loan_id: u64,
@@ -272,11 +635,16 @@ pub mod solignition {
         require!(ctx.accounts.admin.key() == ctx.accounts.protocol_config.admin, ErrorCode::Unauthorized);
         require!(ctx.accounts.loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
         require!(ctx.accounts.loan.program_pubkey == Pubkey::default(), ErrorCode::ProgramAlreadySet);
+
+        if ctx.accounts.loan.state == LoanState::Pending {
+            ctx.accounts.deployer_record.loans_in_flight -= 1;
+        }
         
         ctx.accounts.loan.program_pubkey = program_pubkey;
         ctx.accounts.loan.state = LoanState::Active;
 
         emit_cpi!(LoanDeployed {
+            pool: ctx.accounts.protocol_config.key(),
             loan_id,
             program_pubkey,
         });
@@ -284,8 +652,72 @@ pub mod solignition {
         Ok(())
     }
 
-    /// Repay an active loan with interest
-    pub fn repay_loan(ctx: Context<RepayLoan>, loan_id: u64) -> Result<()> {
+    /// Called by the deployer once the real deploy cost is known. Principal
+    /// left over from the estimate goes back to the vault and the loan is
+    /// reduced to the actual cost.
+    pub fn settle_deployment(ctx: Context<SettleDeployment>, loan_id: u64, actual_cost: u64) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(
+            loan.state == LoanState::Pending || loan.state == LoanState::Active,
+            ErrorCode::LoanNotActive
+        );
+        require!(loan.deployment_cost.is_none(), ErrorCode::DeploymentAlreadySettled);
+        require!(!loan.position_sold, ErrorCode::PositionSold);
+        require!(actual_cost <= loan.principal, ErrorCode::InvalidAmount);
+
+        let refund = loan.principal - actual_cost;
+        require!(refund <= loan.principal_outstanding, ErrorCode::InvalidAmount);
+
+        if refund > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.deployer.key(),
+                &ctx.accounts.vault.key(),
+                refund,
+            );
+            invoke(
+                &ix,
+                &[
+                    ctx.accounts.deployer.to_account_info(),
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        // The refund comes off each tranche's draw like a principal repayment
+        let loan = &mut ctx.accounts.loan;
+        let config = &mut ctx.accounts.protocol_config;
+        let (senior_refund, junior_refund) =
+            split_repayment(refund, loan.senior_principal, loan.junior_principal);
+        let estimated_cost = loan.principal;
+
+        loan.principal = actual_cost;
+        loan.principal_outstanding -= refund;
+        loan.senior_principal -= senior_refund;
+        loan.junior_principal -= junior_refund;
+        loan.deployment_cost = Some(actual_cost);
+        ctx.accounts.deployer_record.lamports_returned += refund;
+
+        config.senior.loans_outstanding -= senior_refund;
+        config.junior.loans_outstanding -= junior_refund;
+        config.total_loans_outstanding -= refund;
+
+        emit_cpi!(DeploymentSettled {
+            pool: config.key(),
+            loan_id,
+            estimated_cost,
+            actual_cost,
+            refunded: refund,
+        });
+
+        Ok(())
+    }
+
+    /// Repay an active loan with interest. Fails if the amount due has grown
+    /// past `max_total_due` since the borrower signed. Any collateral the
+    /// borrower posted is returned to them.
+    pub fn repay_loan(ctx: Context<RepayLoan>, loan_id: u64, max_total_due: u64) -> Result<()> {
     require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
     
     let loan = &ctx.accounts.loan;
@@ -294,619 +726,4243 @@ pub mod solignition {
     require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
 
     let clock = Clock::get()?;
-    let elapsed = (clock.unix_timestamp - loan.start_ts) as u64;
-    
-    // Calculate interest
-    let interest = calculate_interest(
-        loan.principal,
-        loan.interest_rate_bps,
-        elapsed,
-    );
-    
-    let total_due = loan.principal + interest;
-
-    // Transfer repayment from borrower to vault
-    let ix = system_instruction::transfer(
-        &ctx.accounts.borrower.key(),
-        &ctx.accounts.vault.key(),
-        total_due,
-    );
-    invoke(
-        &ix,
-        &[
-            ctx.accounts.borrower.to_account_info(),
-            ctx.accounts.vault.to_account_info(),
-            ctx.accounts.system_program.to_account_info(),
-        ],
+    let payee = repayment_payee(
+        loan,
+        &ctx.accounts.vault,
+        &ctx.accounts.position_proceeds,
     )?;
 
-    // Distribute interest to depositors
-    distribute_yield(&mut ctx.accounts.protocol_config, interest);
-
-    // Update loan state - marked as paid but authority not yet transferred
-    let loan = &mut ctx.accounts.loan;
-    loan.state = LoanState::RepaidPendingTransfer;
-    loan.repaid_ts = Some(clock.unix_timestamp);
-    loan.interest_paid = Some(interest);
+    // Loan is marked as paid but authority not yet transferred.
+    let (total_due, interest, penalty) = collect_full_repayment(
+        &mut ctx.accounts.protocol_config,
+        &mut ctx.accounts.loan,
+        &ctx.accounts.borrower.to_account_info(),
+        &payee,
+        &ctx.accounts.treasury,
+        &ctx.accounts.system_program,
+        max_total_due,
+        clock.unix_timestamp,
+    )?;
 
-    // Update protocol state
-    ctx.accounts.protocol_config.total_loans_outstanding -= loan.principal;
+    let pool = ctx.accounts.protocol_config.key();
+    if returns_collateral_on_repay(&ctx.accounts.loan) {
+        let (Some(loan_collateral), Some(borrower_collateral), Some(token_program)) = (
+            &ctx.accounts.loan_collateral,
+            &ctx.accounts.borrower_collateral,
+            &ctx.accounts.token_program,
+        ) else {
+            return err!(ErrorCode::MissingCollateralAccounts);
+        };
+        let mint = ctx.accounts.loan.collateral_mint.unwrap_or_default();
+        let amount = move_collateral(
+            &pool,
+            &mut ctx.accounts.loan,
+            loan_collateral,
+            borrower_collateral,
+            &ctx.accounts.vault,
+            &ctx.accounts.borrower.to_account_info(),
+            token_program,
+            ctx.bumps.vault,
+        )?;
+        emit_cpi!(CollateralReturned {
+            pool,
+            loan_id,
+            mint,
+            amount,
+        });
+    }
 
     emit_cpi!(LoanRepaid {
-        loan_id: loan.loan_id,
+        pool: ctx.accounts.protocol_config.key(),
+        loan_id,
+        payer: ctx.accounts.borrower.key(),
         total_repaid: total_due,
         interest_paid: interest,
+        penalty_interest: penalty,
     });
 
     Ok(())
 }
-//only called by deployer
-pub fn transfer_authority_to_borrower(
-    ctx: Context<TransferAuthorityToBorrower>,
-    loan_id: u64,
-) -> Result<()> {
-    let loan = &ctx.accounts.loan;
-    
-    require!(
-        loan.state == LoanState::RepaidPendingTransfer,
-        ErrorCode::LoanNotRepaid
-    );
-    require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
-    require!(loan.program_pubkey != Pubkey::default(), ErrorCode::InvalidProgram);
 
-    // Transfer upgrade authority from deployer to borrower
-    let ix = bpf_loader_upgradeable::set_upgrade_authority(
-        &loan.program_pubkey,
-        &ctx.accounts.deployer.key(),
-        Some(&loan.borrower),
-    );
-    
-    invoke(
-        &ix,
-        &[
-            ctx.accounts.program_data.to_account_info(),
-            ctx.accounts.deployer.to_account_info(),
-            ctx.accounts.borrower.to_account_info(),
-            ctx.accounts.bpf_upgradeable_loader.to_account_info(),
-        ],
-    )?;
+    /// Repay an active loan in full on the borrower's behalf. The payer funds
+    /// the repayment; the loan and its upgrade authority still go to the borrower.
+    /// Collateral is returned with `release_collateral`.
+    pub fn repay_on_behalf(ctx: Context<RepayOnBehalf>, loan_id: u64, max_total_due: u64) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
 
-    // Update loan state to fully repaid
-    let loan = &mut ctx.accounts.loan;
-    loan.state = LoanState::Repaid;
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Active, ErrorCode::LoanNotActive);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
 
-    emit_cpi!(AuthorityTransferred {
-        program_pubkey: loan.program_pubkey,
-        new_authority: loan.borrower,
-    });
+        let clock = Clock::get()?;
+        let payee = repayment_payee(
+            loan,
+            &ctx.accounts.vault,
+            &ctx.accounts.position_proceeds,
+        )?;
+        let (total_due, interest, penalty) = collect_full_repayment(
+            &mut ctx.accounts.protocol_config,
+            &mut ctx.accounts.loan,
+            &ctx.accounts.payer.to_account_info(),
+            &payee,
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program,
+            max_total_due,
+            clock.unix_timestamp,
+        )?;
 
-    Ok(())
-}
+        emit_cpi!(LoanRepaid {
+            pool: ctx.accounts.protocol_config.key(),
+            loan_id,
+            payer: ctx.accounts.payer.key(),
+            total_repaid: total_due,
+            interest_paid: interest,
+            penalty_interest: penalty,
+        });
 
-    /// mark expired loan for recovery
-    pub fn recover_loan(ctx: Context<RecoverLoan>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Pay down part of an active loan. Accrued interest is settled first and
+    /// the remainder reduces the outstanding principal. A payment covering
+    /// everything owed repays the loan in full and returns any collateral.
+    pub fn repay_partial(ctx: Context<RepayLoan>, loan_id: u64, amount: u64) -> Result<()> {
         require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
-        
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
         let loan = &ctx.accounts.loan;
-        require!(loan.state == LoanState::Active || loan.state == LoanState::Pending, ErrorCode::LoanNotActive);
-        
+        require!(loan.state == LoanState::Active, ErrorCode::LoanNotActive);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+
         let clock = Clock::get()?;
-        require!(
-            clock.unix_timestamp >= loan.start_ts + loan.duration,
-            ErrorCode::LoanNotExpired
-        );
+        let payee = repayment_payee(
+            loan,
+            &ctx.accounts.vault,
+            &ctx.accounts.position_proceeds,
+        )?;
+        let receipt = collect_partial_repayment(
+            &mut ctx.accounts.protocol_config,
+            &mut ctx.accounts.loan,
+            &ctx.accounts.borrower.to_account_info(),
+            &payee,
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program,
+            amount,
+            clock.unix_timestamp,
+        )?;
 
-        // Note: The protocol maintains upgrade authority of the expired program
-        // The off-chain deployer can close the program account and return SOL via return_reclaimed_sol
-        
-        // Principal is already gone (used for deployment if deployed)
-        // Admin fee was already collected upfront
-        
-        // Split admin fee between depositors and treasury based on config
-        let depositor_share = (loan.admin_fee_paid as u128)
-            .checked_mul(ctx.accounts.protocol_config.admin_fee_split_bps as u128)
-            .unwrap()
-            .checked_div(10_000)
-            .unwrap() as u64;
-        
-        let treasury_share = loan.admin_fee_paid - depositor_share;
-        
-        // Transfer treasury share from admin PDA to treasury
-        if treasury_share > 0 {
-            let admin_seeds = &[ADMIN_SEED, &[ctx.bumps.admin_pda]];
-            let signer = &[&admin_seeds[..]];
-            
-            let ix = system_instruction::transfer(
-            &ctx.accounts.admin_pda.key(),
-            &ctx.accounts.treasury.key(),
-            treasury_share,
-            );
-            invoke_signed(
-                        &ix,
-                &[
-                ctx.accounts.admin_pda.to_account_info(),
-                ctx.accounts.treasury.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-                 ],
-                 signer,
+        let pool = ctx.accounts.protocol_config.key();
+        emit_cpi!(LoanPartiallyRepaid {
+            pool,
+            loan_id,
+            amount: receipt.total_paid,
+            interest_paid: receipt.interest,
+            penalty_interest: receipt.penalty,
+            principal_paid: receipt.principal_paid,
+            principal_outstanding: ctx.accounts.loan.principal_outstanding,
+        });
+
+        if receipt.repaid_in_full && returns_collateral_on_repay(&ctx.accounts.loan) {
+            let (Some(loan_collateral), Some(borrower_collateral), Some(token_program)) = (
+                &ctx.accounts.loan_collateral,
+                &ctx.accounts.borrower_collateral,
+                &ctx.accounts.token_program,
+            ) else {
+                return err!(ErrorCode::MissingCollateralAccounts);
+            };
+            let mint = ctx.accounts.loan.collateral_mint.unwrap_or_default();
+            let amount = move_collateral(
+                &pool,
+                &mut ctx.accounts.loan,
+                loan_collateral,
+                borrower_collateral,
+                &ctx.accounts.vault,
+                &ctx.accounts.borrower.to_account_info(),
+                token_program,
+                ctx.bumps.vault,
             )?;
-           }
-        
-        // Distribute depositor share as yield
-        if depositor_share > 0 {
-            distribute_yield(&mut ctx.accounts.protocol_config, depositor_share);
+            emit_cpi!(CollateralReturned {
+                pool,
+                loan_id,
+                mint,
+                amount,
+            });
         }
 
-        // Update loan state
-        let loan = &mut ctx.accounts.loan;
-        loan.state = LoanState::Recovered;
-        loan.recovered_ts = Some(clock.unix_timestamp);
+        if receipt.repaid_in_full {
+            emit_cpi!(LoanRepaid {
+                pool,
+                loan_id,
+                payer: ctx.accounts.borrower.key(),
+                total_repaid: receipt.total_paid,
+                interest_paid: receipt.interest,
+                penalty_interest: receipt.penalty,
+            });
+        }
 
-        // Update protocol state (principal already deducted at origination)
-        ctx.accounts.protocol_config.total_loans_outstanding -= loan.principal;
+        Ok(())
+    }
 
-        emit_cpi!(LoanRecovered {
-            loan_id: loan.loan_id,
-            admin_fee_distributed: loan.admin_fee_paid,
-            depositor_share,
-            treasury_share,
+    /// Pay down a loan from the financed program itself. The program signs for
+    /// its repayer PDA (`[REPAYER_SEED]` under its own id) through CPI; only
+    /// the program recorded on the loan can pay this way. Works like
+    /// `repay_partial`, and ownership stays with the borrower. Once the loan's
+    /// position is sold, its proceeds escrow follows as a remaining account,
+    /// so the account list stays the same.
+    pub fn repay_from_program<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RepayFromProgram<'info>>,
+        loan_id: u64,
+        amount: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Active, ErrorCode::LoanNotActive);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+
+        let clock = Clock::get()?;
+        let position_proceeds = ctx.remaining_accounts.first().cloned();
+        if let Some(proceeds) = &position_proceeds {
+            let (expected, _) = Pubkey::find_program_address(
+                &[PROCEEDS_SEED, ctx.accounts.loan.key().as_ref()],
+                ctx.program_id,
+            );
+            require!(proceeds.key() == expected, ErrorCode::MissingPositionProceeds);
+        }
+        let payee = repayment_payee(loan, &ctx.accounts.vault, &position_proceeds)?;
+        let receipt = collect_partial_repayment(
+            &mut ctx.accounts.protocol_config,
+            &mut ctx.accounts.loan,
+            &ctx.accounts.repayer.to_account_info(),
+            &payee,
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program,
+            amount,
+            clock.unix_timestamp,
+        )?;
+
+        let pool = ctx.accounts.protocol_config.key();
+        emit_cpi!(LoanPartiallyRepaid {
+            pool,
+            loan_id,
+            amount: receipt.total_paid,
+            interest_paid: receipt.interest,
+            penalty_interest: receipt.penalty,
+            principal_paid: receipt.principal_paid,
+            principal_outstanding: ctx.accounts.loan.principal_outstanding,
         });
 
+        if receipt.repaid_in_full {
+            emit_cpi!(LoanRepaid {
+                pool,
+                loan_id,
+                payer: ctx.accounts.repayer.key(),
+                total_repaid: receipt.total_paid,
+                interest_paid: receipt.interest,
+                penalty_interest: receipt.penalty,
+            });
+        }
+
         Ok(())
     }
 
-    /// Admin function to pause/unpause protocol
-    pub fn set_paused(ctx: Context<AdminAction>, is_paused: bool) -> Result<()> {
-        ctx.accounts.protocol_config.is_paused = is_paused;
-        
-        emit!(ProtocolPausedChanged {
-            is_paused,
+    /// Quote what it would cost to repay a loan in full right now. The quote
+    /// is returned to the caller as return data.
+    pub fn quote_repayment(ctx: Context<QuoteRepayment>, loan_id: u64) -> Result<RepaymentQuote> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Active, ErrorCode::LoanNotActive);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+
+        let clock = Clock::get()?;
+        Ok(quote_full_repayment(&ctx.accounts.protocol_config, loan, clock.unix_timestamp))
+    }
+
+    /// Extend an active loan before it expires. The borrower pays the interest
+    /// accrued so far plus the pool's extension fee, and the loan is repriced
+    /// at the current default interest rate.
+    pub fn extend_loan(ctx: Context<ExtendLoan>, loan_id: u64, additional_duration: i64) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+        require!(additional_duration > 0, ErrorCode::InvalidDuration);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Active, ErrorCode::LoanNotActive);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+
+        require!(!loan.position_sold, ErrorCode::PositionSold);
+
+        let clock = Clock::get()?;
+        require!(clock.unix_timestamp < loan.start_ts + loan.duration, ErrorCode::LoanExpired);
+
+        let config = &ctx.accounts.protocol_config;
+        // Not yet expired, so no penalty has accrued
+        let (interest, _) = accrued_interest(loan, config.penalty_interest_rate_bps, clock.unix_timestamp);
+        let extension_fee = bps_of(loan.principal_outstanding, config.extension_fee_bps);
+        let (depositor_share, treasury_share) = split_fee(extension_fee, config.admin_fee_split_bps);
+
+        // Interest and the depositors' cut of the fee go to the vault
+        let to_vault = interest + depositor_share;
+        if to_vault > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.borrower.key(),
+                &ctx.accounts.vault.key(),
+                to_vault,
+            );
+            invoke(
+                &ix,
+                &[
+                    ctx.accounts.borrower.to_account_info(),
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        if treasury_share > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.borrower.key(),
+                &ctx.accounts.treasury.key(),
+                treasury_share,
+            );
+            invoke(
+                &ix,
+                &[
+                    ctx.accounts.borrower.to_account_info(),
+                    ctx.accounts.treasury.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        // Settle interest to date, then reprice from here on
+        let loan = &mut ctx.accounts.loan;
+        let config = &mut ctx.accounts.protocol_config;
+        apply_repayment(config, loan, interest, 0, 0, clock.unix_timestamp);
+        distribute_yield(config, depositor_share);
+
+        loan.duration += additional_duration;
+        loan.interest_rate_bps = config.default_interest_rate_bps;
+        loan.extension_count += 1;
+
+        emit_cpi!(LoanExtended {
+            pool: config.key(),
+            loan_id,
+            new_duration: loan.duration,
+            interest_rate_bps: loan.interest_rate_bps,
+            interest_paid: interest,
+            extension_fee,
+            extension_count: loan.extension_count,
         });
-        
+
         Ok(())
     }
 
-    /// Return reclaimed SOL from expired/recovered loans back to vault
-    pub fn return_reclaimed_sol(ctx: Context<ReturnReclaimedSol>, amount: u64) -> Result<()> {
+    /// Grow the programdata of a financed program with `ExtendProgram`. The
+    /// vault pays the extra rent, which is added to the loan's principal;
+    /// interest accrued so far is settled first.
+    pub fn top_up_loan(ctx: Context<TopUpLoan>, loan_id: u64, additional_bytes: u32) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+        require!(additional_bytes > 0, ErrorCode::InvalidAmount);
+
         let loan = &ctx.accounts.loan;
-        
-        // Ensure loan has been recovered
-        require!(loan.state == LoanState::Recovered ||
-                 loan.state == LoanState::Pending , ErrorCode::LoanNotRecovered);
-        
-        // Ensure caller is authorized (admin or deployer)
+        require!(loan.state == LoanState::Active, ErrorCode::LoanNotActive);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+
+        require!(!loan.position_sold, ErrorCode::PositionSold);
+
+        let clock = Clock::get()?;
+        require!(clock.unix_timestamp < loan.start_ts + loan.duration, ErrorCode::LoanExpired);
+
+        // Rent the loader will draw from the vault for the larger account
+        let program_data = &ctx.accounts.program_data;
+        let new_len = program_data.data_len() + additional_bytes as usize;
+        let required = Rent::get()?.minimum_balance(new_len).saturating_sub(program_data.lamports());
+
+        let config = &ctx.accounts.protocol_config;
+        let available = config.total_deposits.saturating_sub(config.total_loans_outstanding);
+        require!(required <= available, ErrorCode::InsufficientLiquidity);
+        let (senior_required, junior_required) = split_principal(required, config.senior_draw_bps);
         require!(
-            ctx.accounts.caller.key() == ctx.accounts.protocol_config.admin || 
-            ctx.accounts.caller.key() == ctx.accounts.protocol_config.deployer,
-            ErrorCode::Unauthorized
+            senior_required <= config.senior.available_liquidity() &&
+            junior_required <= config.junior.available_liquidity(),
+            ErrorCode::InsufficientLiquidity
         );
-        
-        // Transfer SOL from deployer back to vault
-        let ix = system_instruction::transfer(
-            &ctx.accounts.deployer.key(),
-            &ctx.accounts.vault.key(),
-            amount,
+
+        // Settle interest on the current principal before it grows
+        let (interest, _) = accrued_interest(loan, config.penalty_interest_rate_bps, clock.unix_timestamp);
+        if interest > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.borrower.key(),
+                &ctx.accounts.vault.key(),
+                interest,
+            );
+            invoke(
+                &ix,
+                &[
+                    ctx.accounts.borrower.to_account_info(),
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        let pool = ctx.accounts.protocol_config.key();
+        let vault_seeds = &[VAULT_SEED, pool.as_ref(), &[ctx.bumps.vault]];
+        let signer = &[&vault_seeds[..]];
+
+        let vault_before = ctx.accounts.vault.lamports();
+        let ix = bpf_loader_upgradeable::extend_program(
+            &loan.program_pubkey,
+            Some(&ctx.accounts.vault.key()),
+            additional_bytes,
         );
-        invoke(
+        invoke_signed(
             &ix,
             &[
-                ctx.accounts.deployer.to_account_info(),
-                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.program_data.to_account_info(),
+                ctx.accounts.program_account.to_account_info(),
                 ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.bpf_upgradeable_loader.to_account_info(),
             ],
+            signer,
         )?;
-        
-        // Update loan record to track reclaimed amount
+
+        // Book what the loader actually drew
+        let amount = vault_before.saturating_sub(ctx.accounts.vault.lamports());
+
         let loan = &mut ctx.accounts.loan;
-        loan.reclaimed_amount = Some(loan.reclaimed_amount.unwrap_or(0) + amount);
-        loan.reclaimed_ts = Some(Clock::get()?.unix_timestamp);
-        
-        emit_cpi!(SolReclaimed {
-            loan_id: loan.loan_id,
+        let config = &mut ctx.accounts.protocol_config;
+        let (senior_principal, junior_principal) = split_principal(amount, config.senior_draw_bps);
+        apply_repayment(config, loan, interest, 0, 0, clock.unix_timestamp);
+
+        loan.principal += amount;
+        loan.principal_outstanding += amount;
+        loan.senior_principal += senior_principal;
+        loan.junior_principal += junior_principal;
+        config.senior.loans_outstanding += senior_principal;
+        config.junior.loans_outstanding += junior_principal;
+        config.total_loans_outstanding += amount;
+
+        emit_cpi!(LoanToppedUp {
+            pool: config.key(),
+            loan_id,
             amount,
-            total_reclaimed: loan.reclaimed_amount.unwrap_or(0),
+            additional_bytes,
+            interest_paid: interest,
+            principal: loan.principal,
+            principal_outstanding: loan.principal_outstanding,
         });
-        
+
         Ok(())
     }
 
-    /// Admin function to update configuration
-    pub fn update_config(
-        ctx: Context<AdminAction>,
-        admin_fee_split_bps: Option<u16>,
-        default_interest_rate_bps: Option<u16>,
-        default_admin_fee_bps: Option<u16>,
-        deployer: Option<Pubkey>,
-        treasury: Option<Pubkey>,
-    ) -> Result<()> {
-        let config = &mut ctx.accounts.protocol_config;
-        
-        if let Some(split) = admin_fee_split_bps {
-            require!(split <= 10000, ErrorCode::InvalidParameter);
-            config.admin_fee_split_bps = split;
-        }
-        
-        if let Some(rate) = default_interest_rate_bps {
-            require!(rate <= 10000, ErrorCode::InvalidParameter);
-            config.default_interest_rate_bps = rate;
-        }
-        
-        if let Some(fee) = default_admin_fee_bps {
-            require!(fee <= 10000, ErrorCode::InvalidParameter);
-            config.default_admin_fee_bps = fee;
+    /// Let the borrower ship an upgrade while the loan is active. The program's
+    /// upgrade authority, and the buffer's authority, must be the pool's
+    /// authority PDA, which signs the loader `Upgrade`. Buffer lamports are
+    /// returned to the borrower.
+    pub fn upgrade_financed_program(ctx: Context<UpgradeFinancedProgram>, loan_id: u64) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Active, ErrorCode::LoanNotActive);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+
+        let clock = Clock::get()?;
+        require!(clock.unix_timestamp < loan.start_ts + loan.duration, ErrorCode::LoanExpired);
+
+        let pool = ctx.accounts.protocol_config.key();
+        let authority_seeds = &[AUTHORITY_SEED, pool.as_ref(), &[ctx.bumps.authority_pda]];
+        let signer = &[&authority_seeds[..]];
+
+        let ix = bpf_loader_upgradeable::upgrade(
+            &loan.program_pubkey,
+            &ctx.accounts.buffer.key(),
+            &ctx.accounts.authority_pda.key(),
+            &ctx.accounts.borrower.key(),
+        );
+        invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.program_data.to_account_info(),
+                ctx.accounts.program_account.to_account_info(),
+                ctx.accounts.buffer.to_account_info(),
+                ctx.accounts.borrower.to_account_info(),
+                ctx.accounts.rent.to_account_info(),
+                ctx.accounts.clock.to_account_info(),
+                ctx.accounts.authority_pda.to_account_info(),
+                ctx.accounts.bpf_upgradeable_loader.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        let programdata_hash = {
+            let program_data = ctx.accounts.program_data.to_account_info();
+            let data = program_data.try_borrow_data()?;
+            programdata_hash(&data)
+        };
+
+        emit_cpi!(FinancedProgramUpgraded {
+            pool,
+            loan_id,
+            program_pubkey: loan.program_pubkey,
+            programdata_hash,
+            upgraded_ts: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Assign an open loan to a new borrower. Both borrowers sign, and so
+    /// does the guarantor of a guaranteed loan; the debt and the right to the
+    /// program's upgrade authority move to the new one. Collateral stays
+    /// owed to whoever posted it.
+    pub fn transfer_loan(ctx: Context<TransferLoan>, loan_id: u64, allowlist_proof: Vec<[u8; 32]>) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(
+            matches!(
+                loan.state,
+                LoanState::AwaitingApproval | LoanState::Pending | LoanState::Active | LoanState::RepaidPendingTransfer
+            ),
+            ErrorCode::LoanNotActive
+        );
+
+        // A guarantor only backs the borrower it agreed to
+        if loan.guarantee_amount > 0 {
+            let Some(guarantor) = &ctx.accounts.guarantor else {
+                return err!(ErrorCode::MissingGuarantor);
+            };
+            require!(loan.guarantor == Some(guarantor.key()), ErrorCode::InvalidGuarantor);
         }
-        
-        if let Some(deployer) = deployer {
-            config.deployer = deployer;
+
+        // The new borrower must be one the pool would lend to
+        if let Some(root) = ctx.accounts.protocol_config.borrower_allowlist_root {
+            require!(
+                verify_merkle_proof(&allowlist_proof, &root, &ctx.accounts.new_borrower.key()),
+                ErrorCode::BorrowerNotAllowlisted
+            );
         }
-        
-        if let Some(treasury) = treasury {
-            config.treasury = treasury;
+
+        let loan = &mut ctx.accounts.loan;
+        let previous_borrower = loan.borrower;
+        loan.borrower = ctx.accounts.new_borrower.key();
+
+        emit_cpi!(LoanTransferred {
+            pool: ctx.accounts.protocol_config.key(),
+            loan_id,
+            previous_borrower,
+            new_borrower: loan.borrower,
+        });
+
+        Ok(())
+    }
+
+    /// Mint a loan's position token, an SPL token with a fixed supply of one
+    /// that carries the right to the loan's repayments. It is minted to the
+    /// vault, so repayments keep going to depositors until it is bought. Only
+    /// the admin or the loan's borrower may mint it.
+    ///
+    /// Positions are minted on demand, for loans the pool means to sell,
+    /// rather than when every loan is originated. The mint, vault token
+    /// account and metadata would add seven accounts to `request_loan`,
+    /// pushing quoted and collateralised requests past the transaction size
+    /// limit, and would charge every borrower their rent. A loan without a
+    /// position is simply held by the vault.
+    pub fn mint_loan_position(ctx: Context<MintLoanPosition>, loan_id: u64) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(
+            matches!(loan.state, LoanState::AwaitingApproval | LoanState::Pending | LoanState::Active),
+            ErrorCode::LoanNotActive
+        );
+
+        let pool = ctx.accounts.protocol_config.key();
+        let authority_seeds = &[AUTHORITY_SEED, pool.as_ref(), &[ctx.bumps.authority_pda]];
+        let signer = &[&authority_seeds[..]];
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.position_mint.to_account_info(),
+                    to: ctx.accounts.vault_position.to_account_info(),
+                    authority: ctx.accounts.authority_pda.to_account_info(),
+                },
+                signer,
+            ),
+            1,
+        )?;
+
+        create_metadata_accounts_v3(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_metadata_program.to_account_info(),
+                CreateMetadataAccountsV3 {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    mint: ctx.accounts.position_mint.to_account_info(),
+                    mint_authority: ctx.accounts.authority_pda.to_account_info(),
+                    payer: ctx.accounts.payer.to_account_info(),
+                    update_authority: ctx.accounts.authority_pda.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+                signer,
+            ),
+            DataV2 {
+                name: format!("Solignition Loan #{}", loan_id),
+                symbol: "SLOAN".to_string(),
+                uri: String::new(),
+                seller_fee_basis_points: 0,
+                creators: None,
+                collection: None,
+                uses: None,
+            },
+            false,
+            true,
+            None,
+        )?;
+
+        // No more can ever be minted
+        token::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                SetAuthority {
+                    current_authority: ctx.accounts.authority_pda.to_account_info(),
+                    account_or_mint: ctx.accounts.position_mint.to_account_info(),
+                },
+                signer,
+            ),
+            AuthorityType::MintTokens,
+            None,
+        )?;
+
+        let mint = ctx.accounts.position_mint.key();
+        ctx.accounts.loan.position_mint = Some(mint);
+
+        emit_cpi!(LoanPositionMinted {
+            pool,
+            loan_id,
+            mint,
+        });
+
+        Ok(())
+    }
+
+    /// Buy a loan's position token from the vault. The price is the
+    /// outstanding principal plus the depositors' share of interest accrued so
+    /// far, and the pool books it as if the loan had been repaid. From then on
+    /// repayments, and whatever is recovered if the loan is written off, are
+    /// paid into the loan's proceeds escrow. Whoever holds the token claims
+    /// them with `claim_position_proceeds` and carries the default risk.
+    pub fn buy_loan_position(ctx: Context<BuyLoanPosition>, loan_id: u64, max_price: u64) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Active, ErrorCode::LoanNotActive);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(!loan.position_sold, ErrorCode::PositionSold);
+
+        let clock = Clock::get()?;
+        let (price, interest) = position_price(&ctx.accounts.protocol_config, loan, clock.unix_timestamp);
+        require!(price <= max_price, ErrorCode::SlippageExceeded);
+
+        let ix = system_instruction::transfer(
+            &ctx.accounts.buyer.key(),
+            &ctx.accounts.vault.key(),
+            price,
+        );
+        invoke(
+            &ix,
+            &[
+                ctx.accounts.buyer.to_account_info(),
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        let pool = ctx.accounts.protocol_config.key();
+        let vault_seeds = &[VAULT_SEED, pool.as_ref(), &[ctx.bumps.vault]];
+        let signer = &[&vault_seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_position.to_account_info(),
+                    to: ctx.accounts.buyer_position.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer,
+            ),
+            1,
+        )?;
+
+        // Open the proceeds escrow rent-exempt so any payment can land in it
+        let top_up = Rent::get()?
+            .minimum_balance(0)
+            .saturating_sub(ctx.accounts.position_proceeds.lamports());
+        if top_up > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.buyer.key(),
+                &ctx.accounts.position_proceeds.key(),
+                top_up,
+            );
+            invoke(
+                &ix,
+                &[
+                    ctx.accounts.buyer.to_account_info(),
+                    ctx.accounts.position_proceeds.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
         }
-        
-        emit_cpi!(ConfigUpdated {
-            admin_fee_split_bps: config.admin_fee_split_bps,
-            default_interest_rate_bps: config.default_interest_rate_bps,
-            default_admin_fee_bps: config.default_admin_fee_bps,
+
+        sell_position(&mut ctx.accounts.protocol_config, &mut ctx.accounts.loan, interest);
+
+        emit_cpi!(LoanPositionSold {
+            pool,
+            loan_id,
+            buyer: ctx.accounts.buyer.key(),
+            price,
+            interest,
         });
-        
+
         Ok(())
     }
-}
 
-/// Helper function to calculate interest
-fn calculate_interest(principal: u64, rate_bps: u16, elapsed_seconds: u64) -> u64 {
-    let interest = (principal as u128)
-        .checked_mul(rate_bps as u128)
-        .unwrap()
-        .checked_mul(elapsed_seconds as u128)
-        .unwrap()
-        .checked_div(10_000u128 * SECONDS_PER_YEAR as u128)
-        .unwrap();
-    
-    interest as u64
-}
+    /// Claim what has been paid into a sold loan's proceeds escrow. Only the
+    /// current holder of the position token may claim; the escrow keeps its
+    /// rent-exempt minimum.
+    pub fn claim_position_proceeds(ctx: Context<ClaimPositionProceeds>, loan_id: u64) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+
+        let amount = ctx
+            .accounts
+            .position_proceeds
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(0));
+        require!(amount > 0, ErrorCode::NoPositionProceeds);
+
+        let loan_key = loan.key();
+        let proceeds_seeds = &[PROCEEDS_SEED, loan_key.as_ref(), &[ctx.bumps.position_proceeds]];
+        let signer = &[&proceeds_seeds[..]];
+
+        let ix = system_instruction::transfer(
+            &ctx.accounts.position_proceeds.key(),
+            &ctx.accounts.holder.key(),
+            amount,
+        );
+        invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.position_proceeds.to_account_info(),
+                ctx.accounts.holder.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        emit_cpi!(PositionProceedsClaimed {
+            pool: ctx.accounts.protocol_config.key(),
+            loan_id,
+            holder: ctx.accounts.holder.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+/// Hand a repaid loan's program upgrade authority from the pool's authority
+/// PDA to the borrower. Anyone may call this.
+pub fn transfer_authority_to_borrower(
+    ctx: Context<TransferAuthorityToBorrower>,
+    loan_id: u64,
+) -> Result<()> {
+    let loan = &ctx.accounts.loan;
+    
+    require!(
+        loan.state == LoanState::RepaidPendingTransfer,
+        ErrorCode::LoanNotRepaid
+    );
+    require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+    require!(loan.program_pubkey != Pubkey::default(), ErrorCode::InvalidProgram);
+
+    // Transfer upgrade authority from the authority PDA to borrower
+    let pool = ctx.accounts.protocol_config.key();
+    let authority_seeds = &[AUTHORITY_SEED, pool.as_ref(), &[ctx.bumps.authority_pda]];
+    let signer = &[&authority_seeds[..]];
+
+    let ix = bpf_loader_upgradeable::set_upgrade_authority(
+        &loan.program_pubkey,
+        &ctx.accounts.authority_pda.key(),
+        Some(&loan.borrower),
+    );
+    
+    invoke_signed(
+        &ix,
+        &[
+            ctx.accounts.program_data.to_account_info(),
+            ctx.accounts.authority_pda.to_account_info(),
+            ctx.accounts.borrower.to_account_info(),
+            ctx.accounts.bpf_upgradeable_loader.to_account_info(),
+        ],
+        signer,
+    )?;
+
+    // Update loan state to fully repaid
+    let loan = &mut ctx.accounts.loan;
+    loan.state = LoanState::Repaid;
+
+    emit_cpi!(AuthorityTransferred {
+        pool: ctx.accounts.protocol_config.key(),
+        loan_id: loan.loan_id,
+        program_pubkey: loan.program_pubkey,
+        new_authority: loan.borrower,
+    });
+
+    Ok(())
+}
+
+    /// Mark an expired loan recovered once its grace period is over. Anyone
+    /// can call this; the caller earns the pool's keeper bounty. Collateral
+    /// stays in escrow through the redemption window; see `seize_collateral`.
+    pub fn recover_loan(ctx: Context<RecoverLoan>) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+        
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Active || loan.state == LoanState::Pending, ErrorCode::LoanNotActive);
+        
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp >= loan.start_ts + loan.duration,
+            ErrorCode::LoanNotExpired
+        );
+        // The borrower can still repay, at the penalty rate, during grace
+        require!(
+            clock.unix_timestamp >= loan.start_ts + loan.duration + ctx.accounts.protocol_config.grace_period,
+            ErrorCode::LoanInGracePeriod
+        );
+
+        // Note: The protocol maintains upgrade authority of the expired program
+        // Its rent can be reclaimed with close_and_reclaim
+
+        // A loan still pending was never deployed: the deployer failed it
+        if loan.state == LoanState::Pending {
+            ctx.accounts.deployer_record.record_failure();
+        }
+
+        let loan = &mut ctx.accounts.loan;
+        let config = &mut ctx.accounts.protocol_config;
+        let outcome = write_off_loan(config, loan, clock.unix_timestamp);
+        config.total_loans_outstanding -= outcome.principal_written_off;
+        let pool = config.key();
+
+        // The guarantee covers the shortfall; the rest goes back to the guarantor
+        if ctx.accounts.loan.guarantee_amount > 0 {
+            let (Some(guarantee_escrow), Some(guarantor)) = (
+                &ctx.accounts.guarantee_escrow,
+                &ctx.accounts.guarantor,
+            ) else {
+                return err!(ErrorCode::MissingGuarantor);
+            };
+            require!(
+                ctx.accounts.loan.guarantor == Some(guarantor.key()),
+                ErrorCode::InvalidGuarantor
+            );
+            let escrow_bump = ctx.bumps.guarantee_escrow.ok_or(ErrorCode::MissingGuarantor)?;
+            let loan_key = ctx.accounts.loan.key();
+
+            pay_from_guarantee_escrow(
+                &loan_key,
+                guarantee_escrow,
+                &ctx.accounts.vault,
+                &ctx.accounts.system_program,
+                escrow_bump,
+                outcome.guarantee_claimed,
+            )?;
+            let released = guarantee_escrow.lamports();
+            pay_from_guarantee_escrow(
+                &loan_key,
+                guarantee_escrow,
+                guarantor,
+                &ctx.accounts.system_program,
+                escrow_bump,
+                released,
+            )?;
+            ctx.accounts.loan.guarantee_amount = 0;
+            ctx.accounts.loan.guarantee_claimed = outcome.guarantee_claimed;
+
+            emit_cpi!(GuaranteeClaimed {
+                pool,
+                loan_id: ctx.accounts.loan.loan_id,
+                guarantor: guarantor.key(),
+                amount: outcome.guarantee_claimed,
+                released,
+            });
+        }
+
+        // Pay the keeper, depositors and treasury their cuts of the escrowed admin fee
+        let admin_seeds = &[ADMIN_SEED, pool.as_ref(), &[ctx.bumps.admin_pda]];
+        let signer = &[&admin_seeds[..]];
+
+        if outcome.keeper_bounty > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.admin_pda.key(),
+                &ctx.accounts.keeper.key(),
+                outcome.keeper_bounty,
+            );
+            invoke_signed(
+                &ix,
+                &[
+                    ctx.accounts.admin_pda.to_account_info(),
+                    ctx.accounts.keeper.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                signer,
+            )?;
+        }
+
+        // The depositors' share was added to deposits, so the vault must hold it
+        if outcome.depositor_share > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.admin_pda.key(),
+                &ctx.accounts.vault.key(),
+                outcome.depositor_share,
+            );
+            invoke_signed(
+                &ix,
+                &[
+                    ctx.accounts.admin_pda.to_account_info(),
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                signer,
+            )?;
+        }
+
+        if outcome.treasury_share > 0 {
+            let ix = system_instruction::transfer(
+            &ctx.accounts.admin_pda.key(),
+            &ctx.accounts.treasury.key(),
+            outcome.treasury_share,
+            );
+            invoke_signed(
+                        &ix,
+                &[
+                ctx.accounts.admin_pda.to_account_info(),
+                ctx.accounts.treasury.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                 ],
+                 signer,
+            )?;
+           }
+
+        let loan = &ctx.accounts.loan;
+        emit_cpi!(LoanRecovered {
+            pool,
+            loan_id: loan.loan_id,
+            keeper: ctx.accounts.keeper.key(),
+            keeper_bounty: outcome.keeper_bounty,
+            admin_fee_distributed: loan.admin_fee_paid,
+            depositor_share: outcome.depositor_share,
+            treasury_share: outcome.treasury_share,
+            junior_loss: outcome.junior_loss,
+            senior_loss: outcome.senior_loss,
+            guarantee_claimed: outcome.guarantee_claimed,
+        });
+
+        Ok(())
+    }
+
+    /// Recover many expired loans at once. Loans are passed writable through
+    /// `remaining_accounts`; any not yet past expiry plus grace are skipped, as
+    /// are guaranteed and never deployed loans, which go through
+    /// `recover_loan`. A `LoansBatchRecovered` event reports how many
+    /// loans were recovered and how many were skipped for each reason.
+    pub fn recover_loans_batch<'info>(ctx: Context<'_, '_, 'info, 'info, RecoverLoansBatch<'info>>) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+        require!(!ctx.remaining_accounts.is_empty(), ErrorCode::InvalidAmount);
+
+        let clock = Clock::get()?;
+        let pool = ctx.accounts.protocol_config.key();
+        let mut principal_written_off = 0u64;
+        let mut keeper_bounty = 0u64;
+        let mut depositor_share = 0u64;
+        let mut treasury_share = 0u64;
+        let mut recovered = 0u32;
+        let mut skipped_not_due = 0u32;
+        let mut skipped_needs_single = 0u32;
+
+        for loan_info in ctx.remaining_accounts.iter() {
+            require!(loan_info.is_writable, ErrorCode::InvalidLoanAccount);
+            let mut loan = Account::<Loan>::try_from(loan_info)?;
+
+            // Must be this pool's loan PDA
+            let expected = Pubkey::create_program_address(
+                &[
+                    LOAN_SEED,
+                    pool.as_ref(),
+                    loan.loan_id.to_le_bytes().as_ref(),
+                    &[loan.bump],
+                ],
+                ctx.program_id,
+            ).map_err(|_| ErrorCode::InvalidLoanAccount)?;
+            require!(loan_info.key() == expected, ErrorCode::InvalidLoanAccount);
+            require!(loan.pool == pool, ErrorCode::PoolMismatch);
+
+            if !is_recoverable(&loan, ctx.accounts.protocol_config.grace_period, clock.unix_timestamp) {
+                skipped_not_due += 1;
+                continue;
+            }
+            // These need the extra accounts only `recover_loan` takes
+            if loan.guarantee_amount > 0 || loan.state == LoanState::Pending {
+                skipped_needs_single += 1;
+                continue;
+            }
+
+            let outcome = write_off_loan(&mut ctx.accounts.protocol_config, &mut loan, clock.unix_timestamp);
+            principal_written_off += outcome.principal_written_off;
+            keeper_bounty += outcome.keeper_bounty;
+            depositor_share += outcome.depositor_share;
+            treasury_share += outcome.treasury_share;
+            loan.exit(ctx.program_id)?;
+            recovered += 1;
+
+            emit_cpi!(LoanRecovered {
+                pool,
+                loan_id: loan.loan_id,
+                keeper: ctx.accounts.keeper.key(),
+                keeper_bounty: outcome.keeper_bounty,
+                admin_fee_distributed: loan.admin_fee_paid,
+                depositor_share: outcome.depositor_share,
+                treasury_share: outcome.treasury_share,
+                junior_loss: outcome.junior_loss,
+                senior_loss: outcome.senior_loss,
+                guarantee_claimed: outcome.guarantee_claimed,
+            });
+        }
+
+        ctx.accounts.protocol_config.total_loans_outstanding -= principal_written_off;
+
+        // Pay the keeper, depositors and treasury for the whole batch in one go
+        let admin_seeds = &[ADMIN_SEED, pool.as_ref(), &[ctx.bumps.admin_pda]];
+        let signer = &[&admin_seeds[..]];
+
+        if keeper_bounty > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.admin_pda.key(),
+                &ctx.accounts.keeper.key(),
+                keeper_bounty,
+            );
+            invoke_signed(
+                &ix,
+                &[
+                    ctx.accounts.admin_pda.to_account_info(),
+                    ctx.accounts.keeper.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                signer,
+            )?;
+        }
+
+        if depositor_share > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.admin_pda.key(),
+                &ctx.accounts.vault.key(),
+                depositor_share,
+            );
+            invoke_signed(
+                &ix,
+                &[
+                    ctx.accounts.admin_pda.to_account_info(),
+                    ctx.accounts.vault.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                signer,
+            )?;
+        }
+
+        if treasury_share > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.admin_pda.key(),
+                &ctx.accounts.treasury.key(),
+                treasury_share,
+            );
+            invoke_signed(
+                &ix,
+                &[
+                    ctx.accounts.admin_pda.to_account_info(),
+                    ctx.accounts.treasury.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                signer,
+            )?;
+        }
+
+        emit_cpi!(LoansBatchRecovered {
+            pool,
+            keeper: ctx.accounts.keeper.key(),
+            recovered,
+            skipped_not_due,
+            skipped_needs_single,
+        });
+
+        Ok(())
+    }
+
+    /// Return a loan's collateral to whoever posted it once the loan no longer
+    /// needs it: repaid, whoever paid, redeemed after recovery, or a request
+    /// that was rejected or expired. Anyone may call this.
+    pub fn release_collateral(ctx: Context<ReleaseCollateral>, loan_id: u64) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(loan.collateral_amount > 0, ErrorCode::NoCollateral);
+        require!(collateral_releasable(loan), ErrorCode::LoanNotRepaid);
+
+        let pool = ctx.accounts.protocol_config.key();
+        let mint = loan.collateral_mint.unwrap_or_default();
+        let amount = move_collateral(
+            &pool,
+            &mut ctx.accounts.loan,
+            &ctx.accounts.loan_collateral,
+            &ctx.accounts.owner_collateral,
+            &ctx.accounts.vault,
+            &ctx.accounts.collateral_owner,
+            &ctx.accounts.token_program,
+            ctx.bumps.vault,
+        )?;
+
+        emit_cpi!(CollateralReturned {
+            pool,
+            loan_id,
+            mint,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Seize a recovered loan's collateral once its redemption window has
+    /// closed without the borrower buying the program back. It goes to a
+    /// token account the treasury owns. Anyone may call this.
+    pub fn seize_collateral(ctx: Context<SeizeCollateral>, loan_id: u64) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(loan.collateral_amount > 0, ErrorCode::NoCollateral);
+        require!(
+            matches!(loan.state, LoanState::Recovered | LoanState::AuthoritySold),
+            ErrorCode::LoanNotRecovered
+        );
+        require!(
+            !in_redemption_window(loan, ctx.accounts.protocol_config.redemption_window, Clock::get()?.unix_timestamp),
+            ErrorCode::RedemptionWindowOpen
+        );
+
+        let pool = ctx.accounts.protocol_config.key();
+        let mint = loan.collateral_mint.unwrap_or_default();
+        let amount = move_collateral(
+            &pool,
+            &mut ctx.accounts.loan,
+            &ctx.accounts.loan_collateral,
+            &ctx.accounts.treasury_collateral,
+            &ctx.accounts.vault,
+            &ctx.accounts.treasury,
+            &ctx.accounts.token_program,
+            ctx.bumps.vault,
+        )?;
+
+        emit_cpi!(CollateralSeized {
+            pool,
+            loan_id,
+            mint,
+            amount,
+            destination: ctx.accounts.treasury_collateral.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Return a loan's guarantee to the guarantor once the loan was repaid,
+    /// or the request rejected or expired. Anyone may call this.
+    pub fn release_guarantee(ctx: Context<ReleaseGuarantee>, loan_id: u64) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(loan.guarantee_amount > 0, ErrorCode::NoGuarantee);
+        require!(
+            matches!(
+                loan.state,
+                LoanState::RepaidPendingTransfer | LoanState::Repaid | LoanState::Rejected | LoanState::Expired
+            ),
+            ErrorCode::LoanNotRepaid
+        );
+
+        let amount = ctx.accounts.guarantee_escrow.lamports();
+        pay_from_guarantee_escrow(
+            &loan.key(),
+            &ctx.accounts.guarantee_escrow,
+            &ctx.accounts.guarantor,
+            &ctx.accounts.system_program,
+            ctx.bumps.guarantee_escrow,
+            amount,
+        )?;
+
+        let loan = &mut ctx.accounts.loan;
+        loan.guarantee_amount = 0;
+
+        emit_cpi!(GuaranteeReleased {
+            pool: ctx.accounts.protocol_config.key(),
+            loan_id,
+            guarantor: ctx.accounts.guarantor.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Pay a guarantor back, from the vault, what was recovered on a loan
+    /// after its guarantee was claimed. Anyone may call this.
+    pub fn refund_guarantor(ctx: Context<RefundGuarantor>, loan_id: u64) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        let amount = loan.guarantee_refund_due;
+        require!(amount > 0, ErrorCode::NoGuaranteeRefund);
+
+        let pool = ctx.accounts.protocol_config.key();
+        let vault_seeds = &[VAULT_SEED, pool.as_ref(), &[ctx.bumps.vault]];
+        let signer = &[&vault_seeds[..]];
+
+        let ix = system_instruction::transfer(
+            &ctx.accounts.vault.key(),
+            &ctx.accounts.guarantor.key(),
+            amount,
+        );
+        invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.guarantor.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        ctx.accounts.loan.guarantee_refund_due = 0;
+
+        emit_cpi!(GuarantorRefunded {
+            pool,
+            loan_id,
+            guarantor: ctx.accounts.guarantor.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Admin function to register a deployer, or to activate or deactivate
+    /// one. New loans are assigned to active deployers in turn; a deactivated
+    /// deployer keeps its record and finishes the loans it holds.
+    pub fn set_deployer(ctx: Context<SetDeployer>, active: bool) -> Result<()> {
+        ctx.accounts.protocol_config.set_deployer_active(ctx.accounts.deployer.key(), active)?;
+
+        let deployer_record = &mut ctx.accounts.deployer_record;
+        if deployer_record.deployer == Pubkey::default() {
+            deployer_record.pool = ctx.accounts.protocol_config.key();
+            deployer_record.deployer = ctx.accounts.deployer.key();
+            deployer_record.registered_ts = Clock::get()?.unix_timestamp;
+            deployer_record.bump = ctx.bumps.deployer_record;
+        }
+        deployer_record.active = active;
+
+        emit_cpi!(DeployerUpdated {
+            pool: deployer_record.pool,
+            deployer: deployer_record.deployer,
+            active,
+        });
+
+        Ok(())
+    }
+
+    /// Admin function to whitelist a collateral mint, or update or disable
+    /// one. Loans must post `collateral_ratio_bps` of their principal in
+    /// collateral, valued at `lamports_per_token` per whole token.
+    pub fn set_collateral_mint(
+        ctx: Context<SetCollateralMint>,
+        collateral_ratio_bps: u16,
+        lamports_per_token: u64,
+        enabled: bool,
+    ) -> Result<()> {
+        require!(collateral_ratio_bps > 0, ErrorCode::InvalidAmount);
+        require!(lamports_per_token > 0, ErrorCode::InvalidAmount);
+
+        let collateral_config = &mut ctx.accounts.collateral_config;
+        collateral_config.pool = ctx.accounts.protocol_config.key();
+        collateral_config.mint = ctx.accounts.mint.key();
+        collateral_config.collateral_ratio_bps = collateral_ratio_bps;
+        collateral_config.lamports_per_token = lamports_per_token;
+        collateral_config.enabled = enabled;
+        collateral_config.bump = ctx.bumps.collateral_config;
+
+        emit_cpi!(CollateralMintUpdated {
+            pool: collateral_config.pool,
+            mint: collateral_config.mint,
+            collateral_ratio_bps,
+            lamports_per_token,
+            enabled,
+        });
+
+        Ok(())
+    }
+
+    /// Admin function to pause/unpause protocol
+    pub fn set_paused(ctx: Context<AdminAction>, is_paused: bool) -> Result<()> {
+        ctx.accounts.protocol_config.is_paused = is_paused;
+        
+        emit!(ProtocolPausedChanged {
+            pool: ctx.accounts.protocol_config.key(),
+            is_paused,
+        });
+        
+        Ok(())
+    }
+
+    /// Close a recovered loan's program through the loader, signed by the
+    /// pool's authority PDA. The programdata rent goes straight to the vault
+    /// and the lamports actually received are recorded on the loan.
+    pub fn close_and_reclaim(ctx: Context<CloseAndReclaim>, loan_id: u64) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Recovered, ErrorCode::LoanNotRecovered);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(
+            !in_redemption_window(loan, ctx.accounts.protocol_config.redemption_window, Clock::get()?.unix_timestamp),
+            ErrorCode::RedemptionWindowOpen
+        );
+
+        // A running auction has first claim on the program. Once it ends
+        // unsold, its account is closed along with the program.
+        let auction_info = ctx.accounts.auction.to_account_info();
+        if auction_info.owner == ctx.program_id {
+            let auction = {
+                let data = auction_info.try_borrow_data()?;
+                AuthorityAuction::try_deserialize(&mut &data[..])?
+            };
+            require!(
+                Clock::get()?.unix_timestamp > auction.start_ts + auction.decay_period,
+                ErrorCode::AuctionStillRunning
+            );
+
+            let admin_info = ctx.accounts.admin.to_account_info();
+            **admin_info.lamports.borrow_mut() += auction_info.lamports();
+            **auction_info.lamports.borrow_mut() = 0;
+            auction_info.assign(&System::id());
+            auction_info.realloc(0, false)?;
+        }
+
+        let pool = ctx.accounts.protocol_config.key();
+        let authority_seeds = &[AUTHORITY_SEED, pool.as_ref(), &[ctx.bumps.authority_pda]];
+        let signer = &[&authority_seeds[..]];
+
+        let payee = repayment_payee(
+            loan,
+            &ctx.accounts.vault,
+            &ctx.accounts.position_proceeds,
+        )?;
+        let payee_before = payee.lamports();
+        let ix = bpf_loader_upgradeable::close_any(
+            &ctx.accounts.program_data.key(),
+            &payee.key(),
+            Some(&ctx.accounts.authority_pda.key()),
+            Some(&loan.program_pubkey),
+        );
+        invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.program_data.to_account_info(),
+                payee.clone(),
+                ctx.accounts.authority_pda.to_account_info(),
+                ctx.accounts.program_account.to_account_info(),
+                ctx.accounts.bpf_upgradeable_loader.to_account_info(),
+            ],
+            signer,
+        )?;
+        let amount = payee.lamports().saturating_sub(payee_before);
+
+        // Reclaimed rent restores the written-off principal, senior losses
+        // first, unless the position was sold and it went to the holder's escrow
+        let loan = &mut ctx.accounts.loan;
+        if !loan.position_sold {
+            book_recovery(&mut ctx.accounts.protocol_config, loan, amount);
+        }
+        loan.reclaimed_amount = Some(loan.reclaimed_amount.unwrap_or(0) + amount);
+        loan.reclaimed_ts = Some(Clock::get()?.unix_timestamp);
+
+        emit_cpi!(SolReclaimed {
+            pool,
+            loan_id,
+            amount,
+            total_reclaimed: loan.reclaimed_amount.unwrap_or(0),
+        });
+
+        Ok(())
+    }
+
+    /// Put a recovered program's upgrade authority up for Dutch auction. The
+    /// price falls linearly from `start_price` to `floor_price` over
+    /// `decay_period` seconds, after which the program can be closed.
+    pub fn start_authority_auction(
+        ctx: Context<StartAuthorityAuction>,
+        loan_id: u64,
+        start_price: u64,
+        floor_price: u64,
+        decay_period: i64,
+    ) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Recovered, ErrorCode::LoanNotRecovered);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+        require!(start_price >= floor_price && decay_period > 0, ErrorCode::InvalidParameter);
+
+        let clock = Clock::get()?;
+        require!(
+            !in_redemption_window(loan, ctx.accounts.protocol_config.redemption_window, clock.unix_timestamp),
+            ErrorCode::RedemptionWindowOpen
+        );
+        let auction = &mut ctx.accounts.auction;
+        auction.pool = ctx.accounts.protocol_config.key();
+        auction.loan = loan.key();
+        auction.program_pubkey = loan.program_pubkey;
+        auction.start_price = start_price;
+        auction.floor_price = floor_price;
+        auction.start_ts = clock.unix_timestamp;
+        auction.decay_period = decay_period;
+        auction.bump = ctx.bumps.auction;
+
+        emit_cpi!(AuthorityAuctionStarted {
+            pool: auction.pool,
+            loan_id,
+            program_pubkey: auction.program_pubkey,
+            start_price,
+            floor_price,
+            start_ts: auction.start_ts,
+            decay_period,
+        });
+
+        Ok(())
+    }
+
+    /// Buy a recovered program's upgrade authority at the current auction
+    /// price. Proceeds go to the vault and restore written-off principal, or
+    /// to the loan's proceeds escrow once its position has been sold.
+    pub fn buy_program_authority(ctx: Context<BuyProgramAuthority>, loan_id: u64, max_price: u64) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Recovered, ErrorCode::LoanNotRecovered);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+
+        let clock = Clock::get()?;
+        let auction = &ctx.accounts.auction;
+        require!(
+            clock.unix_timestamp <= auction.start_ts + auction.decay_period,
+            ErrorCode::AuctionEnded
+        );
+        let price = auction_price(
+            auction.start_price,
+            auction.floor_price,
+            auction.start_ts,
+            auction.decay_period,
+            clock.unix_timestamp,
+        );
+        require!(price <= max_price, ErrorCode::SlippageExceeded);
+
+        let payee = repayment_payee(
+            loan,
+            &ctx.accounts.vault,
+            &ctx.accounts.position_proceeds,
+        )?;
+        let ix = system_instruction::transfer(
+            &ctx.accounts.buyer.key(),
+            &payee.key(),
+            price,
+        );
+        invoke(
+            &ix,
+            &[
+                ctx.accounts.buyer.to_account_info(),
+                payee,
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        // Hand the upgrade authority to the buyer
+        let pool = ctx.accounts.protocol_config.key();
+        let authority_seeds = &[AUTHORITY_SEED, pool.as_ref(), &[ctx.bumps.authority_pda]];
+        let signer = &[&authority_seeds[..]];
+
+        let ix = bpf_loader_upgradeable::set_upgrade_authority(
+            &loan.program_pubkey,
+            &ctx.accounts.authority_pda.key(),
+            Some(&ctx.accounts.buyer.key()),
+        );
+        invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.program_data.to_account_info(),
+                ctx.accounts.authority_pda.to_account_info(),
+                ctx.accounts.buyer.to_account_info(),
+                ctx.accounts.bpf_upgradeable_loader.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        // Proceeds cover the bad debt, senior losses first
+        let loan = &mut ctx.accounts.loan;
+        if !loan.position_sold {
+            book_recovery(&mut ctx.accounts.protocol_config, loan, price);
+        }
+        loan.reclaimed_amount = Some(loan.reclaimed_amount.unwrap_or(0) + price);
+        loan.reclaimed_ts = Some(clock.unix_timestamp);
+        loan.state = LoanState::AuthoritySold;
+
+        emit_cpi!(AuthorityAuctionSettled {
+            pool,
+            loan_id,
+            program_pubkey: loan.program_pubkey,
+            buyer: ctx.accounts.buyer.key(),
+            price,
+        });
+
+        Ok(())
+    }
+
+    /// Buy back a recovered loan's program within the redemption window. The
+    /// borrower pays the written-off principal, interest to date and the
+    /// recovery penalty, and gets the upgrade authority. Once the loan's
+    /// position is sold, its proceeds escrow is paid instead of depositors. Any
+    /// collateral is then returned with `release_collateral`.
+    pub fn redeem_loan(ctx: Context<RedeemLoan>, loan_id: u64, max_total_due: u64) -> Result<()> {
+        require!(!ctx.accounts.protocol_config.is_paused, ErrorCode::ProtocolPaused);
+
+        let loan = &ctx.accounts.loan;
+        require!(loan.state == LoanState::Recovered, ErrorCode::LoanNotRecovered);
+        require!(loan.loan_id == loan_id, ErrorCode::InvalidLoanId);
+
+        let clock = Clock::get()?;
+        let config = &ctx.accounts.protocol_config;
+        require!(
+            in_redemption_window(loan, config.redemption_window, clock.unix_timestamp),
+            ErrorCode::RedemptionWindowClosed
+        );
+
+        // SOL already returned by the deployer is not charged again
+        let principal = loan.principal_outstanding.saturating_sub(loan.reclaimed_amount.unwrap_or(0));
+        let (interest, penalty_interest) = accrued_interest(loan, config.penalty_interest_rate_bps, clock.unix_timestamp);
+        let penalty = penalty_interest + bps_of(loan.principal_outstanding, config.recovery_penalty_bps);
+        let (_, treasury_penalty) = split_fee(penalty, config.admin_fee_split_bps);
+        let total_due = principal + interest + penalty;
+        require!(total_due <= max_total_due, ErrorCode::SlippageExceeded);
+
+        let payee = repayment_payee(
+            loan,
+            &ctx.accounts.vault,
+            &ctx.accounts.position_proceeds,
+        )?;
+        let ix = system_instruction::transfer(
+            &ctx.accounts.borrower.key(),
+            &payee.key(),
+            total_due - treasury_penalty,
+        );
+        invoke(
+            &ix,
+            &[
+                ctx.accounts.borrower.to_account_info(),
+                payee,
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        if treasury_penalty > 0 {
+            let ix = system_instruction::transfer(
+                &ctx.accounts.borrower.key(),
+                &ctx.accounts.treasury.key(),
+                treasury_penalty,
+            );
+            invoke(
+                &ix,
+                &[
+                    ctx.accounts.borrower.to_account_info(),
+                    ctx.accounts.treasury.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        // Hand the upgrade authority back to the borrower
+        let pool = ctx.accounts.protocol_config.key();
+        let authority_seeds = &[AUTHORITY_SEED, pool.as_ref(), &[ctx.bumps.authority_pda]];
+        let signer = &[&authority_seeds[..]];
+
+        let ix = bpf_loader_upgradeable::set_upgrade_authority(
+            &loan.program_pubkey,
+            &ctx.accounts.authority_pda.key(),
+            Some(&loan.borrower),
+        );
+        invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.program_data.to_account_info(),
+                ctx.accounts.authority_pda.to_account_info(),
+                ctx.accounts.borrower.to_account_info(),
+                ctx.accounts.bpf_upgradeable_loader.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        // The loan left total_loans_outstanding when it was written off, so
+        // the principal comes back as restored deposits, senior losses first.
+        // Interest and the depositors' cut of the penalties are yield.
+        let loan = &mut ctx.accounts.loan;
+        let config = &mut ctx.accounts.protocol_config;
+        if !loan.position_sold {
+            book_recovery(config, loan, principal);
+            distribute_yield(config, interest + penalty - treasury_penalty);
+        }
+
+        loan.principal_outstanding = 0;
+        loan.senior_principal = 0;
+        loan.junior_principal = 0;
+        loan.last_accrual_ts = clock.unix_timestamp;
+        loan.interest_paid = Some(loan.interest_paid.unwrap_or(0) + interest + penalty);
+        loan.repaid_ts = Some(clock.unix_timestamp);
+        loan.state = LoanState::Redeemed;
+
+        emit_cpi!(LoanRedeemed {
+            pool,
+            loan_id,
+            principal_paid: principal,
+            interest_paid: interest,
+            penalty_paid: penalty,
+            total_paid: total_due,
+        });
+
+        Ok(())
+    }
+
+    /// Return reclaimed SOL from expired/recovered loans back to vault
+    pub fn return_reclaimed_sol(ctx: Context<ReturnReclaimedSol>, amount: u64) -> Result<()> {
+        let loan = &ctx.accounts.loan;
+        
+        // Ensure loan has been recovered
+        require!(loan.state == LoanState::Recovered ||
+                 loan.state == LoanState::Pending , ErrorCode::LoanNotRecovered);
+        
+        // Ensure caller is authorized (admin or the loan's deployer)
+        require!(
+            ctx.accounts.caller.key() == ctx.accounts.protocol_config.admin || 
+            ctx.accounts.caller.key() == loan.deployer,
+            ErrorCode::Unauthorized
+        );
+        
+        // Transfer SOL from deployer back to vault, or to the proceeds escrow
+        let payee = repayment_payee(
+            loan,
+            &ctx.accounts.vault,
+            &ctx.accounts.position_proceeds,
+        )?;
+        let ix = system_instruction::transfer(
+            &ctx.accounts.deployer.key(),
+            &payee.key(),
+            amount,
+        );
+        invoke(
+            &ix,
+            &[
+                ctx.accounts.deployer.to_account_info(),
+                payee,
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+        
+        ctx.accounts.deployer_record.lamports_returned += amount;
+
+        // SOL returned after recovery restores the written-off principal,
+        // senior losses first
+        let loan = &mut ctx.accounts.loan;
+        if loan.state == LoanState::Recovered && !loan.position_sold {
+            book_recovery(&mut ctx.accounts.protocol_config, loan, amount);
+        }
+
+        // Update loan record to track reclaimed amount
+        loan.reclaimed_amount = Some(loan.reclaimed_amount.unwrap_or(0) + amount);
+        loan.reclaimed_ts = Some(Clock::get()?.unix_timestamp);
+        
+        emit_cpi!(SolReclaimed {
+            pool: ctx.accounts.protocol_config.key(),
+            loan_id: loan.loan_id,
+            amount,
+            total_reclaimed: loan.reclaimed_amount.unwrap_or(0),
+        });
+        
+        Ok(())
+    }
+
+    /// Admin function to update configuration
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_config(
+        ctx: Context<AdminAction>,
+        admin_fee_split_bps: Option<u16>,
+        default_interest_rate_bps: Option<u16>,
+        default_admin_fee_bps: Option<u16>,
+        treasury: Option<Pubkey>,
+        senior_draw_bps: Option<u16>,
+        junior_yield_share_bps: Option<u16>,
+        underwriter: Option<Pubkey>,
+        auto_approve_threshold: Option<u64>,
+        approval_timeout: Option<i64>,
+        quote_signer: Option<Pubkey>,
+        borrower_allowlist_root: Option<[u8; 32]>,
+        extension_fee_bps: Option<u16>,
+        grace_period: Option<i64>,
+        penalty_interest_rate_bps: Option<u16>,
+        min_interest_period: Option<i64>,
+        min_interest_lamports: Option<u64>,
+        keeper_bounty_bps: Option<u16>,
+        redemption_window: Option<i64>,
+        recovery_penalty_bps: Option<u16>,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        
+        if let Some(split) = admin_fee_split_bps {
+            require!(split <= 10000, ErrorCode::InvalidParameter);
+            config.admin_fee_split_bps = split;
+        }
+        
+        if let Some(rate) = default_interest_rate_bps {
+            require!(rate <= 10000, ErrorCode::InvalidParameter);
+            config.default_interest_rate_bps = rate;
+        }
+        
+        if let Some(fee) = default_admin_fee_bps {
+            require!(fee <= 10000, ErrorCode::InvalidParameter);
+            config.default_admin_fee_bps = fee;
+        }
+        
+        if let Some(treasury) = treasury {
+            config.treasury = treasury;
+        }
+
+        if let Some(draw) = senior_draw_bps {
+            require!(draw <= 10000, ErrorCode::InvalidParameter);
+            config.senior_draw_bps = draw;
+        }
+
+        if let Some(share) = junior_yield_share_bps {
+            require!(share <= 10000, ErrorCode::InvalidParameter);
+            config.junior_yield_share_bps = share;
+        }
+
+        if let Some(underwriter) = underwriter {
+            config.underwriter = underwriter;
+        }
+
+        if let Some(threshold) = auto_approve_threshold {
+            config.auto_approve_threshold = threshold;
+        }
+
+        if let Some(timeout) = approval_timeout {
+            require!(timeout > 0, ErrorCode::InvalidParameter);
+            config.approval_timeout = timeout;
+        }
+
+        // Pubkey::default() disables signed quotes
+        if let Some(quote_signer) = quote_signer {
+            config.quote_signer = quote_signer;
+        }
+
+        // An all-zero root removes the allowlist and opens the pool to everyone
+        if let Some(root) = borrower_allowlist_root {
+            config.borrower_allowlist_root = if root == [0u8; 32] { None } else { Some(root) };
+        }
+
+        if let Some(fee) = extension_fee_bps {
+            require!(fee <= 10000, ErrorCode::InvalidParameter);
+            config.extension_fee_bps = fee;
+        }
+
+        if let Some(grace) = grace_period {
+            require!(grace >= 0, ErrorCode::InvalidParameter);
+            config.grace_period = grace;
+        }
+
+        if let Some(rate) = penalty_interest_rate_bps {
+            require!(rate <= 10000, ErrorCode::InvalidParameter);
+            config.penalty_interest_rate_bps = rate;
+        }
+
+        if let Some(period) = min_interest_period {
+            require!(period >= 0, ErrorCode::InvalidParameter);
+            config.min_interest_period = period;
+        }
+
+        if let Some(lamports) = min_interest_lamports {
+            config.min_interest_lamports = lamports;
+        }
+
+        if let Some(bounty) = keeper_bounty_bps {
+            require!(bounty <= 10000, ErrorCode::InvalidParameter);
+            config.keeper_bounty_bps = bounty;
+        }
+
+        if let Some(window) = redemption_window {
+            require!(window >= 0, ErrorCode::InvalidParameter);
+            config.redemption_window = window;
+        }
+
+        if let Some(penalty) = recovery_penalty_bps {
+            require!(penalty <= 10000, ErrorCode::InvalidParameter);
+            config.recovery_penalty_bps = penalty;
+        }
+        
+        emit_cpi!(ConfigUpdated {
+            pool: config.key(),
+            admin_fee_split_bps: config.admin_fee_split_bps,
+            default_interest_rate_bps: config.default_interest_rate_bps,
+            default_admin_fee_bps: config.default_admin_fee_bps,
+            senior_draw_bps: config.senior_draw_bps,
+            junior_yield_share_bps: config.junior_yield_share_bps,
+            auto_approve_threshold: config.auto_approve_threshold,
+            approval_timeout: config.approval_timeout,
+            quote_signer: config.quote_signer,
+            borrower_allowlist_root: config.borrower_allowlist_root,
+            extension_fee_bps: config.extension_fee_bps,
+            grace_period: config.grace_period,
+            penalty_interest_rate_bps: config.penalty_interest_rate_bps,
+            min_interest_period: config.min_interest_period,
+            min_interest_lamports: config.min_interest_lamports,
+            keeper_bounty_bps: config.keeper_bounty_bps,
+            redemption_window: config.redemption_window,
+            recovery_penalty_bps: config.recovery_penalty_bps,
+        });
+        
+        Ok(())
+    }
+}
+
+/// Helper function to calculate interest
+fn calculate_interest(principal: u64, rate_bps: u16, elapsed_seconds: u64) -> u64 {
+    let interest = (principal as u128)
+        .checked_mul(rate_bps as u128)
+        .unwrap()
+        .checked_mul(elapsed_seconds as u128)
+        .unwrap()
+        .checked_div(10_000u128 * SECONDS_PER_YEAR as u128)
+        .unwrap();
+    
+    interest as u64
+}
+
+/// Helper function to calculate the penalty charged on top of the loan rate
+/// for time spent past expiry. Zero unless the penalty rate is the higher one.
+fn calculate_penalty_interest(principal: u64, rate_bps: u16, penalty_rate_bps: u16, overdue_seconds: u64) -> u64 {
+    calculate_interest(principal, penalty_rate_bps, overdue_seconds)
+        .saturating_sub(calculate_interest(principal, rate_bps, overdue_seconds))
+}
+
+/// Helper function to split the time since the last accrual into seconds
+/// before and after the loan's expiry. Returns (elapsed, overdue).
+fn split_accrual_period(last_accrual_ts: i64, expiry_ts: i64, now: i64) -> (u64, u64) {
+    let elapsed = now.saturating_sub(last_accrual_ts).max(0) as u64;
+    let overdue = now.saturating_sub(last_accrual_ts.max(expiry_ts)).max(0) as u64;
+    (elapsed, overdue)
+}
+
+/// Helper function to calculate interest accrued since the last repayment.
+/// Returns (interest at the loan rate, penalty interest while overdue).
+fn accrued_interest(loan: &Loan, penalty_rate_bps: u16, now: i64) -> (u64, u64) {
+    let (elapsed, overdue) = split_accrual_period(loan.last_accrual_ts, loan.start_ts + loan.duration, now);
+    let interest = calculate_interest(loan.principal_outstanding, loan.interest_rate_bps, elapsed);
+    let penalty = calculate_penalty_interest(
+        loan.principal_outstanding,
+        loan.interest_rate_bps,
+        penalty_rate_bps,
+        overdue,
+    );
+    (interest, penalty)
+}
+
+/// Helper function to calculate the least interest a loan is charged over
+/// its life: the configured minimum period at the loan rate, or the flat
+/// minimum, whichever is larger
+fn minimum_interest(config: &ProtocolConfig, loan: &Loan) -> u64 {
+    let period = config.min_interest_period.max(0) as u64;
+    calculate_interest(loan.principal, loan.interest_rate_bps, period).max(config.min_interest_lamports)
+}
+
+/// Helper function to calculate the extra interest needed for a loan being
+/// repaid now to meet its minimum charge
+fn minimum_interest_top_up(minimum: u64, interest_paid: u64, interest_due: u64) -> u64 {
+    minimum.saturating_sub(interest_paid + interest_due)
+}
+
+/// Helper function to price a full repayment of a loan
+fn quote_full_repayment(config: &ProtocolConfig, loan: &Loan, now: i64) -> RepaymentQuote {
+    let (interest, penalty_interest) = accrued_interest(loan, config.penalty_interest_rate_bps, now);
+    let minimum_interest_top_up = minimum_interest_top_up(
+        minimum_interest(config, loan),
+        loan.interest_paid.unwrap_or(0),
+        interest + penalty_interest,
+    );
+
+    RepaymentQuote {
+        loan_id: loan.loan_id,
+        principal: loan.principal_outstanding,
+        interest,
+        penalty_interest,
+        minimum_interest_top_up,
+        total_due: loan.principal_outstanding + interest + penalty_interest + minimum_interest_top_up,
+        as_of_ts: now,
+    }
+}
+
+/// Outcome of a partial repayment
+struct RepaymentReceipt {
+    total_paid: u64,
+    interest: u64,
+    penalty: u64,
+    principal_paid: u64,
+    repaid_in_full: bool,
+}
+
+/// Helper function to collect up to `amount` from `payer` against a loan and
+/// pay it to `payee`. Accrued interest is settled first and the remainder
+/// reduces principal; a payment that clears the loan must also meet the
+/// minimum interest charge.
+#[allow(clippy::too_many_arguments)]
+fn collect_partial_repayment<'info>(
+    config: &mut Account<'info, ProtocolConfig>,
+    loan: &mut Account<'info, Loan>,
+    payer: &AccountInfo<'info>,
+    payee: &AccountInfo<'info>,
+    treasury: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    amount: u64,
+    now: i64,
+) -> Result<RepaymentReceipt> {
+    let (interest, penalty) = accrued_interest(loan, config.penalty_interest_rate_bps, now);
+    let (_, treasury_penalty) = split_fee(penalty, config.admin_fee_split_bps);
+    let mut interest = interest + penalty;
+    require!(amount >= interest, ErrorCode::RepaymentBelowInterest);
+
+    // Any excess over what is owed is not taken
+    let principal_paid = (amount - interest).min(loan.principal_outstanding);
+
+    if principal_paid == loan.principal_outstanding {
+        let paid_so_far = loan.interest_paid.unwrap_or(0);
+        interest += minimum_interest_top_up(minimum_interest(config, loan), paid_so_far, interest);
+        require!(amount >= interest + principal_paid, ErrorCode::RepaymentBelowInterest);
+    }
+    let total_paid = interest + principal_paid;
+
+    // Transfer repayment from payer to the vault or proceeds escrow
+    let ix = system_instruction::transfer(&payer.key(), &payee.key(), total_paid - treasury_penalty);
+    invoke(
+        &ix,
+        &[payer.clone(), payee.clone(), system_program.to_account_info()],
+    )?;
+
+    if treasury_penalty > 0 {
+        let ix = system_instruction::transfer(&payer.key(), &treasury.key(), treasury_penalty);
+        invoke(
+            &ix,
+            &[payer.clone(), treasury.clone(), system_program.to_account_info()],
+        )?;
+    }
+
+    let repaid_in_full = apply_repayment(config, loan, interest, treasury_penalty, principal_paid, now);
+
+    Ok(RepaymentReceipt {
+        total_paid,
+        interest,
+        penalty,
+        principal_paid,
+        repaid_in_full,
+    })
+}
+
+/// Helper function to hash the code held in a programdata account, skipping
+/// the loader's metadata header
+fn programdata_hash(data: &[u8]) -> [u8; 32] {
+    let offset = UpgradeableLoaderState::size_of_programdata_metadata().min(data.len());
+    hashv(&[&data[offset..]]).to_bytes()
+}
+
+/// Helper function to derive the PDA a financed program signs with to repay
+/// its loan through `repay_from_program`
+pub fn repayer_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REPAYER_SEED], program_id)
+}
+
+/// Helper function to collect a full repayment from `payer` for `payee` and
+/// settle the loan. Any penalty is split between depositors and treasury. Returns
+/// (total repaid, interest, penalty interest).
+#[allow(clippy::too_many_arguments)]
+fn collect_full_repayment<'info>(
+    config: &mut Account<'info, ProtocolConfig>,
+    loan: &mut Account<'info, Loan>,
+    payer: &AccountInfo<'info>,
+    payee: &AccountInfo<'info>,
+    treasury: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    max_total_due: u64,
+    now: i64,
+) -> Result<(u64, u64, u64)> {
+    let quote = quote_full_repayment(config, loan, now);
+    let penalty = quote.penalty_interest;
+    let (_, treasury_penalty) = split_fee(penalty, config.admin_fee_split_bps);
+    let interest = quote.interest + penalty + quote.minimum_interest_top_up;
+    require!(quote.total_due <= max_total_due, ErrorCode::SlippageExceeded);
+
+    // Transfer repayment from payer to the vault or proceeds escrow
+    let ix = system_instruction::transfer(&payer.key(), &payee.key(), quote.total_due - treasury_penalty);
+    invoke(
+        &ix,
+        &[payer.clone(), payee.clone(), system_program.to_account_info()],
+    )?;
+
+    if treasury_penalty > 0 {
+        let ix = system_instruction::transfer(&payer.key(), &treasury.key(), treasury_penalty);
+        invoke(
+            &ix,
+            &[payer.clone(), treasury.clone(), system_program.to_account_info()],
+        )?;
+    }
+
+    // Distribute interest to depositors and settle the principal
+    apply_repayment(config, loan, interest, treasury_penalty, quote.principal, now);
+
+    Ok((quote.total_due, interest, penalty))
+}
+
+/// Helper function to split a principal repayment between the tranches in
+/// proportion to what each is still owed. Returns (senior, junior).
+fn split_repayment(principal_paid: u64, senior_outstanding: u64, junior_outstanding: u64) -> (u64, u64) {
+    let total = senior_outstanding + junior_outstanding;
+    if principal_paid >= total {
+        return (senior_outstanding, junior_outstanding);
+    }
+
+    let senior = ((principal_paid as u128) * (senior_outstanding as u128) / (total as u128)) as u64;
+    (senior, principal_paid - senior)
+}
+
+/// Helper function to book a repayment: interest, less the treasury's cut of
+/// any penalty, goes to depositors as yield and principal reduces what the
+/// loan and both tranches have outstanding. Once the loan's position is sold
+/// only the loan is updated. Returns true once the loan is repaid in full.
+fn apply_repayment(
+    config: &mut ProtocolConfig,
+    loan: &mut Loan,
+    interest: u64,
+    treasury_interest: u64,
+    principal_paid: u64,
+    now: i64,
+) -> bool {
+    if !loan.position_sold {
+        distribute_yield(config, interest - treasury_interest);
+    }
+
+    let (senior_paid, junior_paid) =
+        split_repayment(principal_paid, loan.senior_principal, loan.junior_principal);
+    loan.senior_principal -= senior_paid;
+    loan.junior_principal -= junior_paid;
+    loan.principal_outstanding -= principal_paid;
+    loan.last_accrual_ts = now;
+    loan.interest_paid = Some(loan.interest_paid.unwrap_or(0) + interest);
+
+    if !loan.position_sold {
+        config.senior.loans_outstanding -= senior_paid;
+        config.junior.loans_outstanding -= junior_paid;
+        config.total_loans_outstanding -= principal_paid;
+    }
+
+    if loan.principal_outstanding == 0 {
+        loan.state = LoanState::RepaidPendingTransfer;
+        loan.repaid_ts = Some(now);
+        return true;
+    }
+    false
+}
+
+/// Helper function to pick where a repayment or recovery proceeds are paid:
+/// the vault, or once the loan's position is sold its proceeds escrow, which
+/// the holder claims from. Paying never depends on the holder's token account.
+fn repayment_payee<'info>(
+    loan: &Loan,
+    vault: &AccountInfo<'info>,
+    position_proceeds: &Option<AccountInfo<'info>>,
+) -> Result<AccountInfo<'info>> {
+    if !loan.position_sold {
+        return Ok(vault.clone());
+    }
+
+    position_proceeds.clone().ok_or(error!(ErrorCode::MissingPositionProceeds))
+}
+
+/// Helper function to check that a token account holding `amount` of `mint`
+/// and owned by `owner` gives `holder` the loan's position
+fn holds_position(loan: &Loan, mint: &Pubkey, amount: u64, owner: &Pubkey, holder: &Pubkey) -> bool {
+    loan.position_mint == Some(*mint) && amount == 1 && owner == holder
+}
+
+/// Helper function to price a loan's position: outstanding principal plus
+/// the depositors' share of interest accrued so far. Returns (price, interest).
+fn position_price(config: &ProtocolConfig, loan: &Loan, now: i64) -> (u64, u64) {
+    let (interest, penalty) = accrued_interest(loan, config.penalty_interest_rate_bps, now);
+    let (_, treasury_penalty) = split_fee(penalty, config.admin_fee_split_bps);
+    let interest = interest + penalty - treasury_penalty;
+
+    (loan.principal_outstanding + interest, interest)
+}
+
+/// Helper function to book the sale of a loan's position. The pool is paid
+/// out as if the loan were repaid; the loan keeps accruing for the holder.
+fn sell_position(config: &mut ProtocolConfig, loan: &mut Loan, interest: u64) {
+    distribute_yield(config, interest);
+
+    config.senior.loans_outstanding -= loan.senior_principal;
+    config.junior.loans_outstanding -= loan.junior_principal;
+    config.total_loans_outstanding -= loan.principal_outstanding;
+
+    loan.senior_principal = 0;
+    loan.junior_principal = 0;
+    loan.position_sold = true;
+}
+
+/// Helper function to distribute yield to depositors
+fn distribute_yield(config: &mut ProtocolConfig, amount: u64) {
+    if config.total_deposits > 0 && amount > 0 {
+        // Yield raises the value per share of each tranche. Juniors take the
+        // configured share; a tranche with no shares passes its cut to the other.
+        let junior_amount = if config.junior.total_shares == 0 {
+            0
+        } else if config.senior.total_shares == 0 {
+            amount
+        } else {
+            bps_of(amount, config.junior_yield_share_bps)
+        };
+
+        config.junior.total_deposits += junior_amount;
+        config.senior.total_deposits += amount - junior_amount;
+        config.total_deposits += amount;
+        config.total_yield_distributed += amount;
+    }
+}
+
+/// Helper function to fund a loan: draws principal from both tranches and
+/// sends it to the deployer, starting the loan clock
+fn disburse_principal<'info>(
+    config: &mut Account<'info, ProtocolConfig>,
+    loan: &mut Account<'info, Loan>,
+    vault: &AccountInfo<'info>,
+    deployer: &AccountInfo<'info>,
+    deployer_record: &mut DeployerRecord,
+    system_program: &Program<'info, System>,
+    vault_bump: u8,
+) -> Result<()> {
+    let principal = loan.principal;
+
+    // Check vault has sufficient liquidity
+    let available = config.total_deposits.saturating_sub(config.total_loans_outstanding);
+    require!(principal <= available, ErrorCode::InsufficientLiquidity);
+
+    // Each tranche funds its configured share of the principal
+    let (senior_principal, junior_principal) = split_principal(principal, config.senior_draw_bps);
+    require!(
+        senior_principal <= config.senior.available_liquidity() &&
+        junior_principal <= config.junior.available_liquidity(),
+        ErrorCode::InsufficientLiquidity
+    );
+
+    // Transfer principal from vault to deployer
+    // The deployer will handle program deployment off-chain
+    // Any unused or reclaimed SOL can be returned via return_reclaimed_sol
+    let pool = config.key();
+    let vault_seeds = &[VAULT_SEED, pool.as_ref(), &[vault_bump]];
+    let signer = &[&vault_seeds[..]];
+
+    let ix = system_instruction::transfer(&vault.key(), &deployer.key(), principal);
+    invoke_signed(
+        &ix,
+        &[
+            vault.to_account_info(),
+            deployer.to_account_info(),
+            system_program.to_account_info(),
+        ],
+        signer,
+    )?;
+
+    let now = Clock::get()?.unix_timestamp;
+    loan.senior_principal = senior_principal;
+    loan.junior_principal = junior_principal;
+    loan.principal_outstanding = principal;
+    loan.start_ts = now;
+    loan.last_accrual_ts = now;
+    loan.state = LoanState::Pending;
+    deployer_record.record_disbursement(principal);
+
+    config.senior.loans_outstanding += senior_principal;
+    config.junior.loans_outstanding += junior_principal;
+    config.total_loans_outstanding += principal;
+
+    Ok(())
+}
+
+/// Helper function to work out the collateral a loan must post: the ratio's
+/// share of principal, priced at `lamports_per_token` per whole token and
+/// rounded up to the next base unit
+fn required_collateral(principal: u64, ratio_bps: u16, lamports_per_token: u64, decimals: u8) -> Option<u64> {
+    let value = (principal as u128).checked_mul(ratio_bps as u128)?;
+    let units = value.checked_mul(10u128.checked_pow(decimals as u32)?)?;
+    let denominator = 10_000u128.checked_mul(lamports_per_token as u128)?;
+    if denominator == 0 {
+        return None;
+    }
+    u64::try_from(units.div_ceil(denominator)).ok()
+}
+
+/// Helper function to check whether a loan's collateral can go back to the
+/// borrower: the loan was repaid or redeemed, or never funded
+fn collateral_releasable(loan: &Loan) -> bool {
+    matches!(
+        loan.state,
+        LoanState::RepaidPendingTransfer |
+            LoanState::Repaid |
+            LoanState::Redeemed |
+            LoanState::Rejected |
+            LoanState::Expired
+    )
+}
+
+/// Helper function to check whether repaying a loan hands its collateral
+/// straight back. Collateral posted by an earlier borrower of a transferred
+/// loan waits for `release_collateral`, which returns it to its owner.
+fn returns_collateral_on_repay(loan: &Loan) -> bool {
+    loan.collateral_amount > 0 && loan.collateral_owner == loan.borrower
+}
+
+/// Helper function to move a loan's escrowed collateral to `destination` and
+/// close the escrow, signed by the vault. Returns the amount moved.
+#[allow(clippy::too_many_arguments)]
+fn move_collateral<'info>(
+    pool: &Pubkey,
+    loan: &mut Account<'info, Loan>,
+    loan_collateral: &Account<'info, TokenAccount>,
+    destination: &Account<'info, TokenAccount>,
+    vault: &AccountInfo<'info>,
+    rent_destination: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    vault_bump: u8,
+) -> Result<u64> {
+    require!(
+        loan.collateral_mint == Some(destination.mint),
+        ErrorCode::InvalidCollateralAccount
+    );
+
+    let vault_seeds = &[VAULT_SEED, pool.as_ref(), &[vault_bump]];
+    let signer = &[&vault_seeds[..]];
+
+    let amount = loan.collateral_amount;
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: loan_collateral.to_account_info(),
+                to: destination.to_account_info(),
+                authority: vault.clone(),
+            },
+            signer,
+        ),
+        amount,
+    )?;
+
+    token::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: loan_collateral.to_account_info(),
+            destination: rent_destination.clone(),
+            authority: vault.clone(),
+        },
+        signer,
+    ))?;
+
+    loan.collateral_amount = 0;
+    Ok(amount)
+}
+
+/// Helper function to pay out of a loan's guarantee escrow
+fn pay_from_guarantee_escrow<'info>(
+    loan: &Pubkey,
+    guarantee_escrow: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    escrow_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let escrow_seeds = &[GUARANTEE_SEED, loan.as_ref(), &[escrow_bump]];
+    let signer = &[&escrow_seeds[..]];
+
+    let ix = system_instruction::transfer(&guarantee_escrow.key(), &to.key(), amount);
+    invoke_signed(
+        &ix,
+        &[
+            guarantee_escrow.clone(),
+            to.clone(),
+            system_program.to_account_info(),
+        ],
+        signer,
+    )?;
+
+    Ok(())
+}
+
+/// Helper function to return an escrowed admin fee from the admin PDA
+fn refund_escrowed_fee<'info>(
+    config: &Account<'info, ProtocolConfig>,
+    admin_pda: &AccountInfo<'info>,
+    borrower: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    admin_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let pool = config.key();
+    let admin_seeds = &[ADMIN_SEED, pool.as_ref(), &[admin_bump]];
+    let signer = &[&admin_seeds[..]];
+
+    let ix = system_instruction::transfer(&admin_pda.key(), &borrower.key(), amount);
+    invoke_signed(
+        &ix,
+        &[
+            admin_pda.to_account_info(),
+            borrower.to_account_info(),
+            system_program.to_account_info(),
+        ],
+        signer,
+    )?;
+
+    Ok(())
+}
+
+/// Helper function to check that an ed25519 program instruction earlier in
+/// this transaction verified `signer`'s signature over `message`
+fn verify_quote_signature(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> Result<()> {
+    let current_index = load_current_index_checked(instructions)?;
+
+    for index in 0..current_index {
+        let ix = load_instruction_at_checked(index as usize, instructions)?;
+        if ix.program_id == ed25519_program::ID
+            && ed25519_instruction_matches(&ix.data, signer, message)
+        {
+            return Ok(());
+        }
+    }
+
+    err!(ErrorCode::MissingQuoteSignature)
+}
+
+/// Helper function to parse ed25519 program instruction data. Only a single
+/// signature whose offsets point into the instruction itself is accepted.
+fn ed25519_instruction_matches(data: &[u8], signer: &Pubkey, message: &[u8]) -> bool {
+    const HEADER_SIZE: usize = 2;
+    const OFFSETS_SIZE: usize = 14;
+    const SIGNATURE_SIZE: usize = 64;
+
+    if data.len() < HEADER_SIZE + OFFSETS_SIZE || data[0] != 1 {
+        return false;
+    }
+
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let signature_offset = read_u16(2) as usize;
+    let signature_ix_index = read_u16(4);
+    let public_key_offset = read_u16(6) as usize;
+    let public_key_ix_index = read_u16(8);
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+    let message_ix_index = read_u16(14);
+
+    // u16::MAX means "this instruction"; anything else could point at unsigned data
+    if signature_ix_index != u16::MAX || public_key_ix_index != u16::MAX || message_ix_index != u16::MAX {
+        return false;
+    }
+
+    let in_bounds = |offset: usize, size: usize| offset.checked_add(size).is_some_and(|end| end <= data.len());
+    if !in_bounds(signature_offset, SIGNATURE_SIZE)
+        || !in_bounds(public_key_offset, 32)
+        || !in_bounds(message_offset, message_size)
+    {
+        return false;
+    }
+
+    data[public_key_offset..public_key_offset + 32] == signer.to_bytes()
+        && &data[message_offset..message_offset + message_size] == message
+}
+
+/// Helper function to check a borrower against the allowlist Merkle root.
+/// Leaves are sha256(borrower) and each node hashes its children in sorted
+/// order, so proofs need no left/right flags.
+fn verify_merkle_proof(proof: &[[u8; 32]], root: &[u8; 32], borrower: &Pubkey) -> bool {
+    let mut node = hashv(&[borrower.as_ref()]).to_bytes();
+
+    for sibling in proof {
+        node = if node <= *sibling {
+            hashv(&[&node, sibling]).to_bytes()
+        } else {
+            hashv(&[sibling, &node]).to_bytes()
+        };
+    }
+
+    node == *root
+}
+
+/// Helper function to split a fee into (depositor, treasury) shares
+fn split_fee(amount: u64, depositor_split_bps: u16) -> (u64, u64) {
+    let depositor_share = bps_of(amount, depositor_split_bps);
+    (depositor_share, amount - depositor_share)
+}
+
+/// Helper function to take basis points of an amount, rounding down
+fn bps_of(amount: u64, bps: u16) -> u64 {
+    ((amount as u128) * (bps as u128) / 10_000) as u64
+}
+
+/// Helper function to split principal into (senior, junior) draws
+fn split_principal(principal: u64, senior_draw_bps: u16) -> (u64, u64) {
+    let senior = bps_of(principal, senior_draw_bps);
+    (senior, principal - senior)
+}
+
+/// Helper function to price a Dutch auction: the price falls linearly from
+/// the start price to the floor over the decay period
+fn auction_price(start_price: u64, floor_price: u64, start_ts: i64, decay_period: i64, now: i64) -> u64 {
+    let elapsed = now.saturating_sub(start_ts).clamp(0, decay_period) as u128;
+    let drop = (start_price - floor_price) as u128 * elapsed / decay_period as u128;
+    start_price - drop as u64
+}
+
+/// Helper function to check whether a recovered loan can still be redeemed
+/// by its borrower
+fn in_redemption_window(loan: &Loan, redemption_window: i64, now: i64) -> bool {
+    loan.state == LoanState::Recovered &&
+        now <= loan.recovered_ts.unwrap_or(0) + redemption_window
+}
+
+/// Helper function to check whether a loan can be recovered: funded, and
+/// past its expiry plus the pool's grace period
+fn is_recoverable(loan: &Loan, grace_period: i64, now: i64) -> bool {
+    (loan.state == LoanState::Active || loan.state == LoanState::Pending) &&
+        now >= loan.start_ts + loan.duration + grace_period
+}
+
+/// Outcome of writing off an expired loan
+struct RecoveryOutcome {
+    keeper_bounty: u64,
+    depositor_share: u64,
+    treasury_share: u64,
+    junior_loss: u64,
+    senior_loss: u64,
+    principal_written_off: u64,
+    guarantee_claimed: u64,
+}
+
+/// Helper function to write off an expired loan. The escrowed admin fee pays
+/// the keeper bounty, with the rest split between depositors and treasury,
+/// and principal not already returned is covered by any guarantee before it
+/// is absorbed, junior tranche first. Lamport transfers and
+/// `total_loans_outstanding` are left to the caller.
+fn write_off_loan(config: &mut ProtocolConfig, loan: &mut Loan, now: i64) -> RecoveryOutcome {
+    let keeper_bounty = bps_of(loan.admin_fee_paid, config.keeper_bounty_bps);
+    let (depositor_share, treasury_share) =
+        split_fee(loan.admin_fee_paid - keeper_bounty, config.admin_fee_split_bps);
+    distribute_yield(config, depositor_share);
+
+    // A sold position's principal is no longer the pool's to lose
+    let principal_written_off = if loan.position_sold { 0 } else { loan.principal_outstanding };
+    let shortfall = principal_written_off.saturating_sub(loan.reclaimed_amount.unwrap_or(0));
+    let guarantee_claimed = shortfall.min(loan.guarantee_amount);
+    let (junior_loss, senior_loss) = absorb_loss(config, shortfall - guarantee_claimed);
+
+    loan.state = LoanState::Recovered;
+    loan.recovered_ts = Some(now);
+    loan.senior_loss = senior_loss;
+
+    config.senior.loans_outstanding -= loan.senior_principal;
+    config.junior.loans_outstanding -= loan.junior_principal;
+
+    RecoveryOutcome {
+        keeper_bounty,
+        depositor_share,
+        treasury_share,
+        junior_loss,
+        senior_loss,
+        principal_written_off,
+        guarantee_claimed,
+    }
+}
+
+/// Helper function to write off a shortfall, junior tranche first.
+/// Returns (junior_loss, senior_loss).
+fn absorb_loss(config: &mut ProtocolConfig, shortfall: u64) -> (u64, u64) {
+    let junior_loss = shortfall.min(config.junior.total_deposits);
+    let senior_loss = (shortfall - junior_loss).min(config.senior.total_deposits);
+
+    config.junior.total_deposits -= junior_loss;
+    config.senior.total_deposits -= senior_loss;
+    config.total_deposits = config.total_deposits.saturating_sub(junior_loss + senior_loss);
+
+    (junior_loss, senior_loss)
+}
+
+/// Helper function to book SOL recovered after a write-off. What the
+/// guarantee covered is owed back to the guarantor first; the rest restores
+/// depositors, senior losses first.
+fn book_recovery(config: &mut ProtocolConfig, loan: &mut Loan, amount: u64) {
+    let refund = amount.min(loan.guarantee_claimed);
+    loan.guarantee_claimed -= refund;
+    loan.guarantee_refund_due += refund;
+
+    let senior_credit = credit_recovery(config, loan.senior_loss, amount - refund);
+    loan.senior_loss -= senior_credit;
+}
+
+/// Helper function to credit SOL recovered after a write-off, restoring the
+/// senior tranche's loss first. Returns the amount credited to seniors.
+fn credit_recovery(config: &mut ProtocolConfig, senior_loss: u64, amount: u64) -> u64 {
+    let senior_credit = amount.min(senior_loss);
+
+    config.senior.total_deposits += senior_credit;
+    config.junior.total_deposits += amount - senior_credit;
+    config.total_deposits += amount;
+
+    senior_credit
+}
+
+// ===== CONTEXTS =====
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(pool_id: u64)]
+pub struct Initialize<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ProtocolConfig::SIZE,
+        seeds = [PROTOCOL_CONFIG_SEED, pool_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    
+    /// CHECK: Vault PDA for storing deposits
+    #[account(
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    /* */
+    /// CHECK: Authority PDA for program upgrade authority
+    #[account(
+        seeds = [AUTHORITY_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub authority_pda: AccountInfo<'info>,
+    
+    /// CHECK: Admin fee collection PDA
+    #[account(
+        seeds = [ADMIN_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub admin_pda: AccountInfo<'info>,
+    
+    /// CHECK: Treasury PDA
+    #[account(
+        seeds = [TREASURY_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub treasury: AccountInfo<'info>,
+
+    /// CHECK: Deployer that receives funds for deployment
+    pub deployer: AccountInfo<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + DeployerRecord::SIZE,
+        seeds = [DEPLOYER_SEED, protocol_config.key().as_ref(), deployer.key().as_ref()],
+        bump
+    )]
+    pub deployer_record: Account<'info, DeployerRecord>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(tranche: Tranche)]
+pub struct Deposit<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        space = 8 + DepositorRecord::SIZE,
+        seeds = [DEPOSITOR_SEED, protocol_config.key().as_ref(), tranche.seed(), depositor.key().as_ref()],
+        bump
+    )]
+    pub depositor_record: Account<'info, DepositorRecord>,
+    
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(tranche: Tranche)]
+pub struct Withdraw<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [DEPOSITOR_SEED, protocol_config.key().as_ref(), tranche.seed(), depositor.key().as_ref()],
+        bump = depositor_record.bump,
+        constraint = depositor_record.pool == protocol_config.key() @ ErrorCode::PoolMismatch,
+        constraint = depositor_record.owner == depositor.key() @ ErrorCode::UnauthorizedDepositor
+    )]
+    pub depositor_record: Account<'info, DepositorRecord>,
+    
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(
+    principal: u64,
+    duration: i64,
+    interest_rate_bps: u16,
+    admin_fee_bps: u16,
+    quote: Option<LoanQuote>,
+)]
+pub struct RequestLoan<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = borrower,
+        space = 8 + Loan::SIZE,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), protocol_config.loan_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub loan: Account<'info, Loan>,
+    
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    /// CHECK: Admin fee collection PDA
+    #[account(
+        mut,
+        seeds = [ADMIN_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub admin_pda: AccountInfo<'info>,
+    
+    
+    /// CHECK: Deployer wallet - receives funds for deployment; the next
+    /// active deployer in turn
+    #[account(
+        mut,
+        constraint = Some(deployer.key()) == protocol_config.assigned_deployer() @ ErrorCode::DeployerInactive
+    )]
+    pub deployer: AccountInfo<'info>,
+
+    /// Registry entry of the assigned deployer, which must be active
+    #[account(
+        mut,
+        seeds = [DEPLOYER_SEED, protocol_config.key().as_ref(), deployer.key().as_ref()],
+        bump = deployer_record.bump,
+        constraint = deployer_record.active @ ErrorCode::DeployerInactive
+    )]
+    pub deployer_record: Account<'info, DeployerRecord>,
+
+    /// Marks a signed quote as used; only required with a quote
+    #[account(
+        init,
+        payer = borrower,
+        space = 8 + QuoteNonce::SIZE,
+        seeds = [
+            QUOTE_NONCE_SEED,
+            protocol_config.key().as_ref(),
+            borrower.key().as_ref(),
+            quote.as_ref().map_or(0, |q| q.nonce).to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub quote_nonce: Option<Account<'info, QuoteNonce>>,
+
+    /// CHECK: Instructions sysvar, used to find the quote's ed25519 signature check
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<AccountInfo<'info>>,
+
+    /// Whitelisted collateral mint; the collateral accounts below are only
+    /// required with it
+    #[account(
+        seeds = [COLLATERAL_CONFIG_SEED, protocol_config.key().as_ref(), collateral_config.mint.as_ref()],
+        bump = collateral_config.bump
+    )]
+    pub collateral_config: Option<Account<'info, CollateralConfig>>,
+
+    pub collateral_mint: Option<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = borrower_collateral.owner == borrower.key() @ ErrorCode::InvalidCollateralAccount
+    )]
+    pub borrower_collateral: Option<Account<'info, TokenAccount>>,
+
+    /// Escrows the collateral for this loan
+    #[account(
+        init,
+        payer = borrower,
+        seeds = [COLLATERAL_SEED, loan.key().as_ref()],
+        bump,
+        token::mint = collateral_mint,
+        token::authority = vault
+    )]
+    pub loan_collateral: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    /// Co-signs the request and funds the guarantee; only required with one
+    #[account(mut)]
+    pub guarantor: Option<Signer<'info>>,
+
+    /// CHECK: Guarantee escrow PDA of this loan
+    #[account(
+        mut,
+        seeds = [GUARANTEE_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub guarantee_escrow: Option<AccountInfo<'info>>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct ApproveLoan<'info> {
+    #[account(
+        constraint = underwriter.key() == protocol_config.underwriter @ ErrorCode::Unauthorized
+    )]
+    pub underwriter: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Deployer wallet chosen at request - receives funds for deployment
+    #[account(
+        mut,
+        address = loan.deployer @ ErrorCode::Unauthorized
+    )]
+    pub deployer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [DEPLOYER_SEED, protocol_config.key().as_ref(), deployer.key().as_ref()],
+        bump = deployer_record.bump,
+        constraint = deployer_record.active @ ErrorCode::DeployerInactive
+    )]
+    pub deployer_record: Account<'info, DeployerRecord>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct RejectLoan<'info> {
+    #[account(
+        constraint = underwriter.key() == protocol_config.underwriter @ ErrorCode::Unauthorized
+    )]
+    pub underwriter: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        has_one = borrower @ ErrorCode::UnauthorizedBorrower,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    /// CHECK: Borrower receiving the refunded admin fee
+    #[account(mut)]
+    pub borrower: AccountInfo<'info>,
+
+    /// CHECK: Admin fee collection PDA holding the escrowed fee
+    #[account(
+        mut,
+        seeds = [ADMIN_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub admin_pda: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct ExpireLoanRequest<'info> {
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        has_one = borrower @ ErrorCode::UnauthorizedBorrower,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    /// CHECK: Borrower receiving the refunded admin fee
+    #[account(mut)]
+    pub borrower: AccountInfo<'info>,
+
+    /// CHECK: Admin fee collection PDA holding the escrowed fee
+    #[account(
+        mut,
+        seeds = [ADMIN_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub admin_pda: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64, program_pubkey: Pubkey)]
+pub struct SetDeployedProgram<'info> {
+    pub admin: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        has_one = admin @ ErrorCode::Unauthorized,
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [DEPLOYER_SEED, protocol_config.key().as_ref(), loan.deployer.as_ref()],
+        bump = deployer_record.bump
+    )]
+    pub deployer_record: Account<'info, DeployerRecord>,
+
+    /// CHECK: Authority PDA, must already be the program's upgrade authority
+    #[account(
+        seeds = [AUTHORITY_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub authority_pda: AccountInfo<'info>,
+
+    #[account(
+        seeds = [program_pubkey.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(authority_pda.key()) @ ErrorCode::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct SettleDeployment<'info> {
+    /// Deployer wallet holding the disbursed principal
+    #[account(
+        mut,
+        address = loan.deployer @ ErrorCode::Unauthorized
+    )]
+    pub deployer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [DEPLOYER_SEED, protocol_config.key().as_ref(), deployer.key().as_ref()],
+        bump = deployer_record.bump
+    )]
+    pub deployer_record: Account<'info, DeployerRecord>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct RepayLoan<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    
+    #[account(
+        mut,
+        has_one = borrower @ ErrorCode::UnauthorizedBorrower,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+    
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Treasury of this pool, receives its cut of penalty interest
+    #[account(
+        mut,
+        constraint = treasury.key() == protocol_config.treasury @ ErrorCode::Unauthorized
+    )]
+    pub treasury: AccountInfo<'info>,
+    
+    /// CHECK: Proceeds escrow PDA of the loan; only required once its position is sold
+    #[account(
+        mut,
+        seeds = [PROCEEDS_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub position_proceeds: Option<AccountInfo<'info>>,
+
+    /// Collateral escrow of the loan; only required with collateral
+    #[account(
+        mut,
+        seeds = [COLLATERAL_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub loan_collateral: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = borrower_collateral.owner == borrower.key() @ ErrorCode::InvalidCollateralAccount
+    )]
+    pub borrower_collateral: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct RepayOnBehalf<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Treasury of this pool, receives its cut of penalty interest
+    #[account(
+        mut,
+        constraint = treasury.key() == protocol_config.treasury @ ErrorCode::Unauthorized
+    )]
+    pub treasury: AccountInfo<'info>,
+
+    /// CHECK: Proceeds escrow PDA of the loan; only required once its position is sold
+    #[account(
+        mut,
+        seeds = [PROCEEDS_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub position_proceeds: Option<AccountInfo<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct RepayFromProgram<'info> {
+    /// Repayer PDA of the financed program, signed for by that program
+    #[account(
+        mut,
+        seeds = [REPAYER_SEED],
+        bump,
+        seeds::program = loan.program_pubkey
+    )]
+    pub repayer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch,
+        constraint = loan.program_pubkey != Pubkey::default() @ ErrorCode::InvalidProgram
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Treasury of this pool, receives its cut of penalty interest
+    #[account(
+        mut,
+        constraint = treasury.key() == protocol_config.treasury @ ErrorCode::Unauthorized
+    )]
+    pub treasury: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct QuoteRepayment<'info> {
+    #[account(
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct ExtendLoan<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = borrower @ ErrorCode::UnauthorizedBorrower,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Treasury of this pool
+    #[account(
+        mut,
+        constraint = treasury.key() == protocol_config.treasury @ ErrorCode::Unauthorized
+    )]
+    pub treasury: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct TopUpLoan<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = borrower @ ErrorCode::UnauthorizedBorrower,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Vault PDA, pays the loader for the extra rent
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: The financed program
+    #[account(
+        mut,
+        constraint = program_account.key() == loan.program_pubkey @ ErrorCode::InvalidProgram
+    )]
+    pub program_account: AccountInfo<'info>,
+
+    /// CHECK: Program data account for the financed program
+    #[account(
+        mut,
+        seeds = [loan.program_pubkey.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID
+    )]
+    pub program_data: AccountInfo<'info>,
+
+    /// CHECK: BPF Upgradeable Loader program
+    #[account(address = bpf_loader_upgradeable::ID)]
+    pub bpf_upgradeable_loader: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct UpgradeFinancedProgram<'info> {
+    /// Receives the buffer's lamports
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    #[account(
+        has_one = borrower @ ErrorCode::UnauthorizedBorrower,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Authority PDA, upgrade authority of the financed program
+    #[account(
+        seeds = [AUTHORITY_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub authority_pda: AccountInfo<'info>,
+
+    /// CHECK: The financed program
+    #[account(
+        mut,
+        constraint = program_account.key() == loan.program_pubkey @ ErrorCode::InvalidProgram
+    )]
+    pub program_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [loan.program_pubkey.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(authority_pda.key()) @ ErrorCode::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    /// CHECK: Buffer holding the new program, validated by the loader
+    #[account(mut)]
+    pub buffer: AccountInfo<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub clock: Sysvar<'info, Clock>,
+
+    /// CHECK: BPF Upgradeable Loader program
+    #[account(address = bpf_loader_upgradeable::ID)]
+    pub bpf_upgradeable_loader: AccountInfo<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct TransferLoan<'info> {
+    pub borrower: Signer<'info>,
+
+    pub new_borrower: Signer<'info>,
+
+    /// Guarantor of the loan; only required while a guarantee is held
+    pub guarantor: Option<Signer<'info>>,
+
+    #[account(
+        mut,
+        has_one = borrower @ ErrorCode::UnauthorizedBorrower,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct MintLoanPosition<'info> {
+    #[account(
+        mut,
+        constraint = payer.key() == protocol_config.admin || payer.key() == loan.borrower @ ErrorCode::Unauthorized
+    )]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [POSITION_SEED, loan.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = authority_pda
+    )]
+    pub position_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = position_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_position: Account<'info, TokenAccount>,
+
+    /// CHECK: Metadata account of the position mint, created by the metadata program
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), position_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Vault PDA, holds the position until it is sold
+    #[account(
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Authority PDA, mint and update authority of the position
+    #[account(
+        seeds = [AUTHORITY_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub authority_pda: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct BuyLoanPosition<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        constraint = loan.position_mint == Some(position_mint.key()) @ ErrorCode::PositionNotMinted
+    )]
+    pub position_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = position_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_position: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = position_mint,
+        associated_token::authority = buyer
+    )]
+    pub buyer_position: Account<'info, TokenAccount>,
+
+    /// CHECK: Vault PDA, receives the price
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Proceeds escrow PDA of the loan, opened by the buyer
+    #[account(
+        mut,
+        seeds = [PROCEEDS_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub position_proceeds: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct ClaimPositionProceeds<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    #[account(
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// Holder's position token account
+    #[account(
+        constraint = holds_position(
+            &loan,
+            &position_token.mint,
+            position_token.amount,
+            &position_token.owner,
+            &holder.key()
+        ) @ ErrorCode::InvalidPositionHolder
+    )]
+    pub position_token: Account<'info, TokenAccount>,
+
+    /// CHECK: Proceeds escrow PDA of the loan
+    #[account(
+        mut,
+        seeds = [PROCEEDS_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub position_proceeds: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct TransferAuthorityToBorrower<'info> {
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+    
+    /// CHECK: Borrower who will receive authority
+    #[account(
+        mut,
+        address = loan.borrower @ ErrorCode::UnauthorizedBorrower
+    )]
+    pub borrower: AccountInfo<'info>,
+
+    /// CHECK: Authority PDA, upgrade authority of the financed program
+    #[account(
+        seeds = [AUTHORITY_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub authority_pda: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [loan.program_pubkey.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(authority_pda.key()) @ ErrorCode::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    /// CHECK: BPF Upgradeable Loader program
+    #[account(address = bpf_loader_upgradeable::ID)]
+    pub bpf_upgradeable_loader: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RecoverLoan<'info> {
+    /// Anyone may recover an eligible loan; receives the keeper bounty
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan.loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+    
+    /// CHECK: Admin fee PDA
+    #[account(
+        mut,
+        seeds = [ADMIN_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub admin_pda: AccountInfo<'info>,
+    
+    /// CHECK: Treasury of this pool
+    #[account(
+        mut,
+        constraint = treasury.key() == protocol_config.treasury @ ErrorCode::Unauthorized
+    )]
+    pub treasury: AccountInfo<'info>,
+
+    /// Registry entry of the loan's deployer
+    #[account(
+        mut,
+        seeds = [DEPLOYER_SEED, protocol_config.key().as_ref(), loan.deployer.as_ref()],
+        bump = deployer_record.bump
+    )]
+    pub deployer_record: Account<'info, DeployerRecord>,
+
+    /// CHECK: Vault PDA, receives the depositors' fee share and any guarantee claim
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Guarantee escrow PDA of the loan; only required with a guarantee
+    #[account(
+        mut,
+        seeds = [GUARANTEE_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub guarantee_escrow: Option<AccountInfo<'info>>,
+
+    /// CHECK: Guarantor recorded on the loan, gets back what the shortfall left
+    #[account(mut)]
+    pub guarantor: Option<AccountInfo<'info>>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RecoverLoansBatch<'info> {
+    /// Anyone may recover eligible loans; receives the keeper bounties
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Admin fee PDA
+    #[account(
+        mut,
+        seeds = [ADMIN_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub admin_pda: AccountInfo<'info>,
+
+    /// CHECK: Vault PDA, receives the depositors' fee share
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Treasury of this pool
+    #[account(
+        mut,
+        constraint = treasury.key() == protocol_config.treasury @ ErrorCode::Unauthorized
+    )]
+    pub treasury: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct CloseAndReclaim<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        has_one = admin @ ErrorCode::Unauthorized,
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    /// CHECK: Vault PDA, receives the programdata rent
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Authority PDA, upgrade authority of the recovered program
+    #[account(
+        seeds = [AUTHORITY_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub authority_pda: AccountInfo<'info>,
+
+    /// CHECK: The recovered program
+    #[account(
+        mut,
+        constraint = program_account.key() == loan.program_pubkey @ ErrorCode::InvalidProgram
+    )]
+    pub program_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [loan.program_pubkey.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(authority_pda.key()) @ ErrorCode::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    /// CHECK: Auction PDA for this loan; empty unless an auction was started
+    #[account(
+        mut,
+        seeds = [AUCTION_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub auction: UncheckedAccount<'info>,
+
+    /// CHECK: Proceeds escrow PDA of the loan; only required once its position is sold
+    #[account(
+        mut,
+        seeds = [PROCEEDS_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub position_proceeds: Option<AccountInfo<'info>>,
+
+    /// CHECK: BPF Upgradeable Loader program
+    #[account(address = bpf_loader_upgradeable::ID)]
+    pub bpf_upgradeable_loader: AccountInfo<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct StartAuthorityAuction<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        has_one = admin @ ErrorCode::Unauthorized,
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + AuthorityAuction::SIZE,
+        seeds = [AUCTION_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub auction: Account<'info, AuthorityAuction>,
 
-/// Helper function to distribute yield to depositors
-fn distribute_yield(config: &mut ProtocolConfig, amount: u64) {
-    if config.total_deposits > 0 && amount > 0 {
-        // This increases the value per share for all depositors
-        // In a real implementation, you'd update a yield_per_share variable
-        // that gets factored into withdrawal calculations
-        config.total_yield_distributed += amount;
-    }
+    /// CHECK: Authority PDA, upgrade authority of the recovered program
+    #[account(
+        seeds = [AUTHORITY_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub authority_pda: AccountInfo<'info>,
+
+    #[account(
+        seeds = [loan.program_pubkey.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(authority_pda.key()) @ ErrorCode::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
 }
 
-// ===== CONTEXTS =====
 #[event_cpi]
 #[derive(Accounts)]
-pub struct Initialize<'info> {
+#[instruction(loan_id: u64)]
+pub struct BuyProgramAuthority<'info> {
+    /// Pays the auction price and becomes the program's upgrade authority
     #[account(mut)]
-    pub admin: Signer<'info>,
-    
+    pub buyer: Signer<'info>,
+
     #[account(
-        init,
-        payer = admin,
-        space = 8 + ProtocolConfig::SIZE,
-        seeds = [PROTOCOL_CONFIG_SEED],
-        bump
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
-    
-    /// CHECK: Vault PDA for storing deposits
+
+    #[account(
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        mut,
+        seeds = [AUCTION_SEED, loan.key().as_ref()],
+        bump = auction.bump,
+        close = admin
+    )]
+    pub auction: Account<'info, AuthorityAuction>,
+
+    /// CHECK: Pool admin, paid the auction account's rent back
+    #[account(
+        mut,
+        address = protocol_config.admin @ ErrorCode::Unauthorized
+    )]
+    pub admin: AccountInfo<'info>,
+
+    /// CHECK: Vault PDA, receives the proceeds
     #[account(
-        seeds = [VAULT_SEED],
+        mut,
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
         bump
     )]
     pub vault: AccountInfo<'info>,
-    /* */
-    /// CHECK: Authority PDA for program upgrade authority
+
+    /// CHECK: Proceeds escrow PDA of the loan; only required once its position is sold
     #[account(
-        seeds = [AUTHORITY_SEED],
+        mut,
+        seeds = [PROCEEDS_SEED, loan.key().as_ref()],
         bump
     )]
-    pub authority_pda: AccountInfo<'info>,
-    
-    /// CHECK: Admin fee collection PDA
+    pub position_proceeds: Option<AccountInfo<'info>>,
+
+    /// CHECK: Authority PDA, upgrade authority of the recovered program
     #[account(
-        seeds = [ADMIN_SEED],
+        seeds = [AUTHORITY_SEED, protocol_config.key().as_ref()],
         bump
     )]
-    pub admin_pda: AccountInfo<'info>,
-    
-    /// CHECK: Treasury PDA
+    pub authority_pda: AccountInfo<'info>,
+
     #[account(
-        seeds = [TREASURY_SEED],
-        bump
+        mut,
+        seeds = [loan.program_pubkey.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(authority_pda.key()) @ ErrorCode::Unauthorized
     )]
-    pub treasury: AccountInfo<'info>,
+    pub program_data: Account<'info, ProgramData>,
+
+    /// CHECK: BPF Upgradeable Loader program
+    #[account(address = bpf_loader_upgradeable::ID)]
+    pub bpf_upgradeable_loader: AccountInfo<'info>,
 
-    /// CHECK: Deployer that receives funds for deployment
-    pub deployer: AccountInfo<'info>,
-    
     pub system_program: Program<'info, System>,
 }
 
 #[event_cpi]
 #[derive(Accounts)]
-pub struct Deposit<'info> {
+#[instruction(loan_id: u64)]
+pub struct RedeemLoan<'info> {
     #[account(mut)]
-    pub depositor: Signer<'info>,
-    
+    pub borrower: Signer<'info>,
+
     #[account(
-        init_if_needed,
-        payer = depositor,
-        space = 8 + DepositorRecord::SIZE,
-        seeds = [DEPOSITOR_SEED, depositor.key().as_ref()],
-        bump
+        mut,
+        has_one = borrower @ ErrorCode::UnauthorizedBorrower,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
     )]
-    pub depositor_record: Account<'info, DepositorRecord>,
-    
+    pub loan: Account<'info, Loan>,
+
     #[account(
         mut,
-        seeds = [PROTOCOL_CONFIG_SEED],
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
-    
+
     /// CHECK: Vault PDA
     #[account(
         mut,
-        seeds = [VAULT_SEED],
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
         bump
     )]
     pub vault: AccountInfo<'info>,
-    
+
+    /// CHECK: Treasury of this pool, receives its cut of the penalties
+    #[account(
+        mut,
+        constraint = treasury.key() == protocol_config.treasury @ ErrorCode::Unauthorized
+    )]
+    pub treasury: AccountInfo<'info>,
+
+    /// CHECK: Proceeds escrow PDA of the loan; only required once its position is sold
+    #[account(
+        mut,
+        seeds = [PROCEEDS_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub position_proceeds: Option<AccountInfo<'info>>,
+
+    /// CHECK: Authority PDA, upgrade authority of the recovered program
+    #[account(
+        seeds = [AUTHORITY_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub authority_pda: AccountInfo<'info>,
+
+    /// Fails to load once the program has been closed
+    #[account(
+        mut,
+        seeds = [loan.program_pubkey.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(authority_pda.key()) @ ErrorCode::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    /// CHECK: BPF Upgradeable Loader program
+    #[account(address = bpf_loader_upgradeable::ID)]
+    pub bpf_upgradeable_loader: AccountInfo<'info>,
+
     pub system_program: Program<'info, System>,
 }
 
 #[event_cpi]
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
-    #[account(mut)]
-    pub depositor: Signer<'info>,
+pub struct ReturnReclaimedSol<'info> {
+    pub caller: Signer<'info>,
     
     #[account(
         mut,
-        seeds = [DEPOSITOR_SEED, depositor.key().as_ref()],
-        bump = depositor_record.bump,
-        constraint = depositor_record.owner == depositor.key() @ ErrorCode::UnauthorizedDepositor
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        bump = protocol_config.bump,
+        constraint = caller.key() == protocol_config.admin || caller.key() == loan.deployer @ ErrorCode::Unauthorized
     )]
-    pub depositor_record: Account<'info, DepositorRecord>,
+    pub protocol_config: Account<'info, ProtocolConfig>,
     
     #[account(
         mut,
-        seeds = [PROTOCOL_CONFIG_SEED],
-        bump = protocol_config.bump
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan.loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
     )]
-    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub loan: Account<'info, Loan>,
     
     /// CHECK: Vault PDA
     #[account(
         mut,
-        seeds = [VAULT_SEED],
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
         bump
     )]
     pub vault: AccountInfo<'info>,
     
+    /// Deployer of the loan, which holds the reclaimed SOL
+    #[account(
+        mut,
+        constraint = deployer.key() == loan.deployer @ ErrorCode::Unauthorized
+    )]
+    pub deployer: Signer<'info>,
+
+    /// Registry entry of the deployer returning the SOL
+    #[account(
+        mut,
+        seeds = [DEPLOYER_SEED, protocol_config.key().as_ref(), deployer.key().as_ref()],
+        bump = deployer_record.bump
+    )]
+    pub deployer_record: Account<'info, DeployerRecord>,
+
+    /// CHECK: Proceeds escrow PDA of the loan; only required once its position is sold
+    #[account(
+        mut,
+        seeds = [PROCEEDS_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub position_proceeds: Option<AccountInfo<'info>>,
+    
     pub system_program: Program<'info, System>,
 }
 
 #[event_cpi]
 #[derive(Accounts)]
-//#[instruction(loan_id: u64)]
-pub struct RequestLoan<'info> {
-    #[account(mut)]
-    pub borrower: Signer<'info>,
-    
+#[instruction(loan_id: u64)]
+pub struct ReleaseCollateral<'info> {
     #[account(
-        init,
-        payer = borrower,
-        space = 8 + Loan::SIZE,
-        seeds = [LOAN_SEED, protocol_config.loan_counter.to_le_bytes().as_ref(), &borrower.key().to_bytes()],
-        bump
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
     )]
     pub loan: Account<'info, Loan>,
-    
+
     #[account(
-        mut,
-        seeds = [PROTOCOL_CONFIG_SEED],
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
-    
-    /// CHECK: Vault PDA
+
+    /// CHECK: Vault PDA, signs for the collateral escrow
     #[account(
-        mut,
-        seeds = [VAULT_SEED],
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
         bump
     )]
     pub vault: AccountInfo<'info>,
-    
-    /// CHECK: Admin fee collection PDA
+
     #[account(
         mut,
-        seeds = [ADMIN_SEED],
+        seeds = [COLLATERAL_SEED, loan.key().as_ref()],
         bump
     )]
-    pub admin_pda: AccountInfo<'info>,
-    
-    
-    /// CHECK: Deployer wallet - receives funds for deployment
+    pub loan_collateral: Account<'info, TokenAccount>,
+
     #[account(
         mut,
-        constraint = deployer.key() == protocol_config.deployer @ ErrorCode::Unauthorized
+        constraint = owner_collateral.owner == loan.collateral_owner @ ErrorCode::InvalidCollateralAccount
     )]
-    pub deployer: AccountInfo<'info>,
-    
-    pub system_program: Program<'info, System>,
+    pub owner_collateral: Account<'info, TokenAccount>,
+
+    /// CHECK: Whoever posted the collateral, gets the escrow's rent back
+    #[account(
+        mut,
+        address = loan.collateral_owner @ ErrorCode::InvalidCollateralAccount
+    )]
+    pub collateral_owner: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
 }
 
 #[event_cpi]
 #[derive(Accounts)]
 #[instruction(loan_id: u64)]
-pub struct SetDeployedProgram<'info> {
-    pub admin: Signer<'info>,
-    
+pub struct SeizeCollateral<'info> {
     #[account(
         mut,
-        seeds = [PROTOCOL_CONFIG_SEED],
-        has_one = admin @ ErrorCode::Unauthorized,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
+    )]
+    pub loan: Account<'info, Loan>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
-    
+
+    /// CHECK: Vault PDA, signs for the collateral escrow
+    #[account(
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [COLLATERAL_SEED, loan.key().as_ref()],
+        bump
+    )]
+    pub loan_collateral: Account<'info, TokenAccount>,
+
+    /// Treasury-owned token account that receives the seized collateral
     #[account(
         mut,
-        seeds = [LOAN_SEED, loan_id.to_le_bytes().as_ref(), &loan.borrower.to_bytes()],
-        bump = loan.bump
+        constraint = treasury_collateral.owner == protocol_config.treasury @ ErrorCode::InvalidCollateralAccount
     )]
-    pub loan: Account<'info, Loan>,
+    pub treasury_collateral: Account<'info, TokenAccount>,
+
+    /// CHECK: Treasury of this pool, gets the escrow's rent back
+    #[account(
+        mut,
+        constraint = treasury.key() == protocol_config.treasury @ ErrorCode::Unauthorized
+    )]
+    pub treasury: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
 }
 
 #[event_cpi]
 #[derive(Accounts)]
 #[instruction(loan_id: u64)]
-pub struct RepayLoan<'info> {
-    #[account(mut)]
-    pub borrower: Signer<'info>,
-    
+pub struct ReleaseGuarantee<'info> {
     #[account(
         mut,
-        has_one = borrower @ ErrorCode::UnauthorizedBorrower,
-        seeds = [LOAN_SEED, loan_id.to_le_bytes().as_ref(), &borrower.key().to_bytes()],
-        bump = loan.bump
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
     )]
     pub loan: Account<'info, Loan>,
-    
+
     #[account(
-        mut,
-        seeds = [PROTOCOL_CONFIG_SEED],
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
-    
-    /// CHECK: Vault PDA
+
+    /// CHECK: Guarantee escrow PDA of the loan
     #[account(
         mut,
-        seeds = [VAULT_SEED],
+        seeds = [GUARANTEE_SEED, loan.key().as_ref()],
         bump
     )]
-    pub vault: AccountInfo<'info>,
-    
+    pub guarantee_escrow: AccountInfo<'info>,
+
+    /// CHECK: Guarantor recorded on the loan
+    #[account(
+        mut,
+        constraint = loan.guarantor == Some(guarantor.key()) @ ErrorCode::InvalidGuarantor
+    )]
+    pub guarantor: AccountInfo<'info>,
+
     pub system_program: Program<'info, System>,
 }
 
 #[event_cpi]
 #[derive(Accounts)]
 #[instruction(loan_id: u64)]
-pub struct TransferAuthorityToBorrower<'info> {
-    /// CHECK: Deployer wallet that currently controls the program upgrade authority
+pub struct RefundGuarantor<'info> {
     #[account(
-        constraint = deployer.key() == protocol_config.deployer @ ErrorCode::Unauthorized
+        mut,
+        seeds = [LOAN_SEED, protocol_config.key().as_ref(), loan_id.to_le_bytes().as_ref()],
+        bump = loan.bump,
+        constraint = loan.pool == protocol_config.key() @ ErrorCode::PoolMismatch
     )]
-    pub deployer: Signer<'info>,
-    
+    pub loan: Account<'info, Loan>,
+
     #[account(
-        seeds = [PROTOCOL_CONFIG_SEED],
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
-    
+
+    /// CHECK: Vault PDA, holds the recovered SOL owed to the guarantor
     #[account(
         mut,
-        seeds = [LOAN_SEED, loan_id.to_le_bytes().as_ref(), &loan.borrower.to_bytes()],
-        bump = loan.bump
+        seeds = [VAULT_SEED, protocol_config.key().as_ref()],
+        bump
     )]
-    pub loan: Account<'info, Loan>,
-    
-    /// CHECK: Borrower who will receive authority
-    #[account(mut)]
-    pub borrower: AccountInfo<'info>,
-    
-    /// CHECK: Program data account for the deployed program
-    #[account(mut)] 
-    pub program_data: AccountInfo<'info>,
+    pub vault: AccountInfo<'info>,
+
+    /// CHECK: Guarantor recorded on the loan
+    #[account(
+        mut,
+        constraint = loan.guarantor == Some(guarantor.key()) @ ErrorCode::InvalidGuarantor
+    )]
+    pub guarantor: AccountInfo<'info>,
 
-    /// CHECK: BPF Upgradeable Loader program
-    #[account(address = bpf_loader_upgradeable::ID)]
-    pub bpf_upgradeable_loader: AccountInfo<'info>,
-    
     pub system_program: Program<'info, System>,
 }
 
 #[event_cpi]
 #[derive(Accounts)]
-pub struct RecoverLoan<'info> {
+pub struct SetDeployer<'info> {
+    #[account(mut)]
     pub admin: Signer<'info>,
-    
+
     #[account(
         mut,
-        seeds = [PROTOCOL_CONFIG_SEED],
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
         has_one = admin @ ErrorCode::Unauthorized,
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
-    
-    #[account(
-        mut,
-        seeds = [LOAN_SEED, loan.loan_id.to_le_bytes().as_ref(), &loan.borrower.to_bytes()],
-        bump = loan.bump
-    )]
-    pub loan: Account<'info, Loan>,
 
-    /// CHECK: Deployer wallet that currently controls the program
-    #[account(
-        constraint = deployer.key() == protocol_config.deployer @ ErrorCode::Unauthorized
-    )]
-    pub deployer: Signer<'info>,  // Must sign to transfer authority
-    
-    /// CHECK: Admin fee PDA
+    /// CHECK: Deployer wallet being registered
+    pub deployer: AccountInfo<'info>,
+
     #[account(
-        mut,
-        seeds = [ADMIN_SEED],
+        init_if_needed,
+        payer = admin,
+        space = 8 + DeployerRecord::SIZE,
+        seeds = [DEPLOYER_SEED, protocol_config.key().as_ref(), deployer.key().as_ref()],
         bump
     )]
-    pub admin_pda: AccountInfo<'info>,
-    
-    /// CHECK: Treasury PDA
-    #[account(mut)]
-    pub treasury: AccountInfo<'info>,
-    
+    pub deployer_record: Account<'info, DeployerRecord>,
+
     pub system_program: Program<'info, System>,
 }
 
 #[event_cpi]
 #[derive(Accounts)]
-pub struct ReturnReclaimedSol<'info> {
-    pub caller: Signer<'info>,
-    
+pub struct SetCollateralMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
     #[account(
-        seeds = [PROTOCOL_CONFIG_SEED],
-        bump = protocol_config.bump,
-        constraint = caller.key() == protocol_config.admin || caller.key() == protocol_config.deployer @ ErrorCode::Unauthorized
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
+        has_one = admin @ ErrorCode::Unauthorized,
+        bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
-    
-    #[account(
-        mut,
-        seeds = [LOAN_SEED, loan.loan_id.to_le_bytes().as_ref(), &loan.borrower.to_bytes()],
-        bump = loan.bump
-    )]
-    pub loan: Account<'info, Loan>,
-    
-    /// CHECK: Vault PDA
+
+    pub mint: Account<'info, Mint>,
+
     #[account(
-        mut,
-        seeds = [VAULT_SEED],
+        init_if_needed,
+        payer = admin,
+        space = 8 + CollateralConfig::SIZE,
+        seeds = [COLLATERAL_CONFIG_SEED, protocol_config.key().as_ref(), mint.key().as_ref()],
         bump
     )]
-    pub vault: AccountInfo<'info>,
-    
-    /// CHECK: Deployer that holds reclaimed SOL may not need this
-    #[account(mut)]
-    pub deployer: Signer<'info>,
-    
+    pub collateral_config: Account<'info, CollateralConfig>,
+
     pub system_program: Program<'info, System>,
 }
 
@@ -917,7 +4973,7 @@ pub struct AdminAction<'info> {
     
     #[account(
         mut,
-        seeds = [PROTOCOL_CONFIG_SEED],
+        seeds = [PROTOCOL_CONFIG_SEED, protocol_config.pool_id.to_le_bytes().as_ref()],
         has_one = admin @ ErrorCode::Unauthorized,
         bump = protocol_config.bump
     )]
@@ -928,14 +4984,33 @@ pub struct AdminAction<'info> {
 
 #[account]
 pub struct ProtocolConfig {
+    pub pool_id: u64,
     pub admin: Pubkey,
     pub treasury: Pubkey,
-    pub deployer: Pubkey,
+    pub active_deployers: Vec<Pubkey>, // New loans are assigned to these in turn
+    pub next_deployer: u8,             // Index of the next deployer in `active_deployers`
     pub admin_fee_split_bps: u16,      // % to depositors vs treasury
     pub default_interest_rate_bps: u16,
     pub default_admin_fee_bps: u16,
-    pub total_deposits: u64,
-    pub total_loans_outstanding: u64,
+    pub senior_draw_bps: u16,          // % of each loan funded by seniors
+    pub junior_yield_share_bps: u16,   // % of depositor yield credited to juniors
+    pub senior: TrancheState,
+    pub junior: TrancheState,
+    pub underwriter: Pubkey,           // Reviews loans above the auto-approve threshold
+    pub auto_approve_threshold: u64,   // Largest principal funded without review
+    pub approval_timeout: i64,         // Seconds before an unreviewed request expires
+    pub quote_signer: Pubkey,          // Signs off-chain loan quotes; default disables quotes
+    pub borrower_allowlist_root: Option<[u8; 32]>, // Merkle root of allowed borrowers
+    pub extension_fee_bps: u16,        // Fee on outstanding principal to extend a loan
+    pub grace_period: i64,             // Seconds after expiry before a loan can be recovered
+    pub penalty_interest_rate_bps: u16, // Rate charged on overdue loans
+    pub min_interest_period: i64,      // Interest is charged for at least this many seconds
+    pub min_interest_lamports: u64,    // Smallest interest charge per loan
+    pub keeper_bounty_bps: u16,        // Share of a recovered loan's admin fee paid to the keeper
+    pub redemption_window: i64,        // Seconds after recovery the borrower can buy the program back
+    pub recovery_penalty_bps: u16,     // Penalty on principal charged to redeem a recovered loan
+    pub total_deposits: u64,           // Sum of both tranches
+    pub total_loans_outstanding: u64,  // Sum of both tranches
     pub total_yield_distributed: u64,
     pub loan_counter: u64,
     pub is_paused: bool,
@@ -943,118 +5018,585 @@ pub struct ProtocolConfig {
 }
 
 impl ProtocolConfig {
-    pub const SIZE: usize = 32 + 32 + 32 + 2 + 2 + 2 + 8 + 8 + 8 + 8 + 1 + 8;
+    pub const SIZE: usize = 8 + 32 + 32 + (4 + 32 * MAX_ACTIVE_DEPLOYERS) + 1 + 2 + 2 + 2 + 2 + 2 + TrancheState::SIZE * 2 + 32 + 8 + 8 + 32 + 33 + 2 + 8 + 2 + 8 + 8 + 2 + 8 + 2 + 8 + 8 + 8 + 8 + 1 + 8;
+
+    pub fn tranche(&self, tranche: Tranche) -> &TrancheState {
+        match tranche {
+            Tranche::Senior => &self.senior,
+            Tranche::Junior => &self.junior,
+        }
+    }
+
+    pub fn tranche_mut(&mut self, tranche: Tranche) -> &mut TrancheState {
+        match tranche {
+            Tranche::Senior => &mut self.senior,
+            Tranche::Junior => &mut self.junior,
+        }
+    }
+
+    /// Deployer the next loan request is assigned to
+    pub fn assigned_deployer(&self) -> Option<Pubkey> {
+        self.active_deployers.get(self.next_deployer as usize).copied()
+    }
+
+    /// Move the assignment on to the next active deployer
+    pub fn advance_deployer(&mut self) {
+        if !self.active_deployers.is_empty() {
+            self.next_deployer = ((self.next_deployer as usize + 1) % self.active_deployers.len()) as u8;
+        }
+    }
+
+    /// Add a deployer to the assignment rotation, or take it out
+    pub fn set_deployer_active(&mut self, deployer: Pubkey, active: bool) -> Result<()> {
+        if active {
+            if !self.active_deployers.contains(&deployer) {
+                require!(
+                    self.active_deployers.len() < MAX_ACTIVE_DEPLOYERS,
+                    ErrorCode::TooManyDeployers
+                );
+                self.active_deployers.push(deployer);
+            }
+        } else {
+            self.active_deployers.retain(|d| *d != deployer);
+            if self.next_deployer as usize >= self.active_deployers.len() {
+                self.next_deployer = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct TrancheState {
+    pub total_deposits: u64,      // Value owed to depositors, net of yield and losses
+    pub total_shares: u64,
+    pub loans_outstanding: u64,   // Principal this tranche has lent out
+}
+
+impl TrancheState {
+    pub const SIZE: usize = 8 + 8 + 8;
+
+    /// SOL the tranche can lend or pay out right now
+    pub fn available_liquidity(&self) -> u64 {
+        self.total_deposits.saturating_sub(self.loans_outstanding)
+    }
+
+    /// Shares minted for a deposit; None if losses have wiped out existing shares
+    pub fn shares_for_deposit(&self, amount: u64) -> Option<u64> {
+        if self.total_shares == 0 {
+            return Some(amount);
+        }
+        if self.total_deposits == 0 {
+            return None;
+        }
+        Some(((amount as u128) * (self.total_shares as u128) / (self.total_deposits as u128)) as u64)
+    }
+
+    /// Lamports paid out when redeeming shares
+    pub fn lamports_for_shares(&self, shares: u64) -> u64 {
+        if self.total_shares == 0 {
+            return 0;
+        }
+        ((shares as u128) * (self.total_deposits as u128) / (self.total_shares as u128)) as u64
+    }
+}
+
+#[derive(Debug)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Tranche {
+    Senior,
+    Junior,
+}
+
+impl Tranche {
+    pub fn seed(&self) -> &'static [u8] {
+        match self {
+            Tranche::Senior => SENIOR_SEED,
+            Tranche::Junior => JUNIOR_SEED,
+        }
+    }
 }
 
 #[account]
 pub struct DepositorRecord {
     pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub tranche: Tranche,
     pub deposited_amount: u64,
     pub share_amount: u64,      // Can differ from deposited due to yield
     pub last_update_ts: i64,
     pub bump: u8,
 }
 
-impl DepositorRecord {
-    pub const SIZE: usize = 32 + 8 + 8 + 8 + 8;
+impl DepositorRecord {
+    pub const SIZE: usize = 32 + 32 + 1 + 8 + 8 + 8 + 8;
+}
+
+/// Registry entry for one of a pool's deployers, so the SOL each one holds
+/// can be audited
+#[account]
+pub struct DeployerRecord {
+    pub pool: Pubkey,
+    pub deployer: Pubkey,
+    pub active: bool,             // Only active deployers are sent new loans
+    pub lamports_received: u64,   // Principal disbursed to this deployer
+    pub lamports_returned: u64,   // Settlement refunds and reclaimed SOL sent back
+    pub loans_in_flight: u64,     // Disbursed but not yet deployed
+    pub loans_failed: u64,        // Recovered without ever being deployed
+    pub registered_ts: i64,
+    pub bump: u8,
+}
+
+impl DeployerRecord {
+    pub const SIZE: usize = 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8 + 1;
+
+    /// SOL the deployer received and has not sent back
+    pub fn lamports_held(&self) -> u64 {
+        self.lamports_received.saturating_sub(self.lamports_returned)
+    }
+
+    /// Book principal sent to the deployer for a new deployment
+    pub fn record_disbursement(&mut self, principal: u64) {
+        self.lamports_received += principal;
+        self.loans_in_flight += 1;
+    }
+
+    /// Book a loan recovered before it was ever deployed
+    pub fn record_failure(&mut self) {
+        self.loans_in_flight = self.loans_in_flight.saturating_sub(1);
+        self.loans_failed += 1;
+    }
+}
+
+#[account]
+pub struct Loan {
+    pub loan_id: u64,
+    pub pool: Pubkey,
+    pub borrower: Pubkey,
+    pub deployer: Pubkey,        // Registered deployer assigned at request
+    pub program_pubkey: Pubkey,  // Set after deployment
+    pub principal: u64,
+    pub principal_outstanding: u64, // Principal not yet repaid
+    pub senior_principal: u64,   // Outstanding principal drawn from the senior tranche
+    pub junior_principal: u64,   // Outstanding principal drawn from the junior tranche
+    pub senior_loss: u64,        // Senior write-off not yet restored by reclaimed SOL
+    pub duration: i64,
+    pub interest_rate_bps: u16,
+    pub admin_fee_bps: u16,
+    pub admin_fee_paid: u64,
+    pub extension_count: u16,
+    pub requested_ts: i64,
+    pub start_ts: i64,           // Set when principal is disbursed
+    pub last_accrual_ts: i64,    // Interest accrues on outstanding principal from here
+    pub state: LoanState,
+    pub authority_pda: Pubkey,
+    pub repaid_ts: Option<i64>,
+    pub recovered_ts: Option<i64>,
+    pub interest_paid: Option<u64>,
+    pub reclaimed_amount: Option<u64>,
+    pub reclaimed_ts: Option<i64>,
+    pub deployment_cost: Option<u64>, // Actual deploy cost, once settled by the deployer
+    pub position_mint: Option<Pubkey>, // Position token, once minted
+    pub position_sold: bool,     // Repayments go to the proceeds escrow instead of the vault
+    pub collateral_mint: Option<Pubkey>,
+    pub collateral_amount: u64,  // Held in the loan's collateral escrow
+    pub collateral_owner: Pubkey, // Posted the collateral and gets it back, even after a transfer
+    pub guarantor: Option<Pubkey>,
+    pub guarantee_amount: u64,   // Held in the loan's guarantee escrow
+    pub guarantee_claimed: u64,  // Claimed at recovery and not yet won back
+    pub guarantee_refund_due: u64, // Recovered since, owed back to the guarantor
+    pub bump: u8,
+}
+
+impl Loan {
+    pub const SIZE: usize = 8 + 32 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 2 + 2 + 8 + 2 + 8 + 8 + 8 + 1 + 32 + 9 + 9 + 9 + 9 + 9 + 9 + 33 + 1 + 33 + 8 + 32 + 33 + 8 + 8 + 8 + 8;
+}
+
+/// Loan terms priced off-chain. The quote signer signs the borsh
+/// serialization of this struct.
+#[derive(Debug)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct LoanQuote {
+    pub pool: Pubkey,
+    pub borrower: Pubkey,
+    pub principal: u64,
+    pub interest_rate_bps: u16,
+    pub admin_fee_bps: u16,
+    pub duration: i64,
+    pub expiry: i64,
+    pub nonce: u64,
+}
+
+/// Breakdown of what a full repayment would cost at `as_of_ts`
+#[derive(Debug)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct RepaymentQuote {
+    pub loan_id: u64,
+    pub principal: u64,
+    pub interest: u64,
+    pub penalty_interest: u64,
+    pub minimum_interest_top_up: u64,
+    pub total_due: u64,
+    pub as_of_ts: i64,
+}
+
+#[account]
+pub struct QuoteNonce {
+    pub pool: Pubkey,
+    pub borrower: Pubkey,
+    pub nonce: u64,
+    pub used_ts: i64,
+    pub bump: u8,
+}
+
+impl QuoteNonce {
+    pub const SIZE: usize = 32 + 32 + 8 + 8 + 1;
+}
+
+/// Collateral terms for one whitelisted mint in a pool
+#[account]
+pub struct CollateralConfig {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub collateral_ratio_bps: u16,   // Collateral value required, as a share of principal
+    pub lamports_per_token: u64,     // Admin-set price of one whole token
+    pub enabled: bool,
+    pub bump: u8,
+}
+
+impl CollateralConfig {
+    pub const SIZE: usize = 32 + 32 + 2 + 8 + 1 + 1;
+}
+
+/// Dutch auction of a recovered program's upgrade authority
+#[account]
+pub struct AuthorityAuction {
+    pub pool: Pubkey,
+    pub loan: Pubkey,
+    pub program_pubkey: Pubkey,
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub start_ts: i64,
+    pub decay_period: i64,  // Seconds for the price to fall to the floor
+    pub bump: u8,
+}
+
+impl AuthorityAuction {
+    pub const SIZE: usize = 32 + 32 + 32 + 8 + 8 + 8 + 8 + 1;
+}
+
+#[derive(Debug)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum LoanState {
+    Active,
+    Repaid,
+    Recovered,
+    Pending,
+    RepaidPendingTransfer,
+    AwaitingApproval,
+    Rejected,
+    Expired,
+    AuthoritySold,
+    Redeemed,
+}
+
+// ===== EVENTS =====
+
+#[event]
+pub struct ProtocolInitialized {
+    pub pool: Pubkey,
+    pub pool_id: u64,
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+}
+
+#[event]
+pub struct Deposited {
+    pub pool: Pubkey,
+    pub depositor: Pubkey,
+    pub tranche: Tranche,
+    pub amount: u64,
+    pub shares: u64,
+    pub total_deposits: u64,
+}
+
+#[event]
+pub struct Withdrawn {
+    pub pool: Pubkey,
+    pub depositor: Pubkey,
+    pub tranche: Tranche,
+    pub amount: u64,
+    pub shares: u64,
+    pub remaining_balance: u64,
+}
+
+#[event]
+pub struct LoanRequested {
+    pub pool: Pubkey,
+    pub borrower: Pubkey,
+    pub deployer: Pubkey,
+    pub loan_id: u64,
+    pub principal: u64,
+    pub senior_principal: u64,
+    pub junior_principal: u64,
+    pub duration: i64,
+    pub interest_rate_bps: u16,
+    pub admin_fee: u64,
+    pub awaiting_approval: bool,
+    pub quote_nonce: Option<u64>,
+    pub collateral_mint: Option<Pubkey>,
+    pub collateral_amount: u64,
+    pub guarantor: Option<Pubkey>,
+    pub guarantee_amount: u64,
+}
+
+#[event]
+pub struct LoanApproved {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub underwriter: Pubkey,
+    pub senior_principal: u64,
+    pub junior_principal: u64,
+}
+
+#[event]
+pub struct LoanRejected {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub underwriter: Pubkey,
+    pub refunded_fee: u64,
+}
+
+#[event]
+pub struct LoanRequestExpired {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub refunded_fee: u64,
+}
+
+#[event]
+pub struct DeploymentSettled {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub estimated_cost: u64,
+    pub actual_cost: u64,
+    pub refunded: u64,
+}
+
+#[event]
+pub struct LoanDeployed {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub program_pubkey: Pubkey,
+}
+
+#[event]
+pub struct LoanRepaid {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub payer: Pubkey,
+    pub total_repaid: u64,
+    pub interest_paid: u64,
+    pub penalty_interest: u64,
+}
+
+#[event]
+pub struct LoanPartiallyRepaid {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub amount: u64,
+    pub interest_paid: u64,
+    pub penalty_interest: u64,
+    pub principal_paid: u64,
+    pub principal_outstanding: u64,
+}
+
+#[event]
+pub struct LoanExtended {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub new_duration: i64,
+    pub interest_rate_bps: u16,
+    pub interest_paid: u64,
+    pub extension_fee: u64,
+    pub extension_count: u16,
+}
+
+#[event]
+pub struct LoanToppedUp {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub amount: u64,
+    pub additional_bytes: u32,
+    pub interest_paid: u64,
+    pub principal: u64,
+    pub principal_outstanding: u64,
+}
+
+#[event]
+pub struct FinancedProgramUpgraded {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub program_pubkey: Pubkey,
+    pub programdata_hash: [u8; 32],
+    pub upgraded_ts: i64,
+}
+
+#[event]
+pub struct AuthorityAuctionStarted {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub program_pubkey: Pubkey,
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub start_ts: i64,
+    pub decay_period: i64,
+}
+
+#[event]
+pub struct AuthorityAuctionSettled {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub program_pubkey: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+}
+
+#[event]
+pub struct LoanTransferred {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub previous_borrower: Pubkey,
+    pub new_borrower: Pubkey,
+}
+
+#[event]
+pub struct LoanPositionMinted {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub mint: Pubkey,
 }
 
-#[account]
-pub struct Loan {
+#[event]
+pub struct LoanPositionSold {
+    pub pool: Pubkey,
     pub loan_id: u64,
-    pub borrower: Pubkey,
-    pub program_pubkey: Pubkey,  // Set after deployment
-    pub principal: u64,
-    pub duration: i64,
-    pub interest_rate_bps: u16,
-    pub admin_fee_bps: u16,
-    pub admin_fee_paid: u64,
-    pub start_ts: i64,
-    pub state: LoanState,
-    pub authority_pda: Pubkey,
-    pub repaid_ts: Option<i64>,
-    pub recovered_ts: Option<i64>,
-    pub interest_paid: Option<u64>,
-    pub reclaimed_amount: Option<u64>,
-    pub reclaimed_ts: Option<i64>,
-    pub bump: u8,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub interest: u64,
 }
 
-impl Loan {
-    pub const SIZE: usize = 8 + 32 + 32 + 8 + 8 + 2 + 2 + 8 + 8 + 1 + 32 + 9 + 9 + 9 + 9 + 9 + 8;
+#[event]
+pub struct PositionProceedsClaimed {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub holder: Pubkey,
+    pub amount: u64,
 }
 
-#[derive(Debug)]
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
-pub enum LoanState {
-    Active,
-    Repaid,
-    Recovered,
-    Pending,
-    RepaidPendingTransfer,
+#[event]
+pub struct CollateralReturned {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub mint: Pubkey,
+    pub amount: u64,
 }
 
-// ===== EVENTS =====
+#[event]
+pub struct CollateralSeized {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub destination: Pubkey,
+}
 
 #[event]
-pub struct ProtocolInitialized {
-    pub admin: Pubkey,
-    pub treasury: Pubkey,
+pub struct CollateralMintUpdated {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub collateral_ratio_bps: u16,
+    pub lamports_per_token: u64,
+    pub enabled: bool,
 }
 
 #[event]
-pub struct Deposited {
-    pub depositor: Pubkey,
+pub struct GuaranteeClaimed {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub guarantor: Pubkey,
     pub amount: u64,
-    pub total_deposits: u64,
+    pub released: u64,
 }
 
 #[event]
-pub struct Withdrawn {
-    pub depositor: Pubkey,
+pub struct GuaranteeReleased {
+    pub pool: Pubkey,
+    pub loan_id: u64,
+    pub guarantor: Pubkey,
     pub amount: u64,
-    pub remaining_balance: u64,
 }
 
 #[event]
-pub struct LoanRequested {
-    pub borrower: Pubkey,
+pub struct GuarantorRefunded {
+    pub pool: Pubkey,
     pub loan_id: u64,
-    pub principal: u64,
-    pub duration: i64,
-    pub interest_rate_bps: u16,
-    pub admin_fee: u64,
+    pub guarantor: Pubkey,
+    pub amount: u64,
 }
 
 #[event]
-pub struct LoanDeployed {
-    pub loan_id: u64,
-    pub program_pubkey: Pubkey,
+pub struct DeployerUpdated {
+    pub pool: Pubkey,
+    pub deployer: Pubkey,
+    pub active: bool,
 }
 
 #[event]
-pub struct LoanRepaid {
+pub struct LoanRedeemed {
+    pub pool: Pubkey,
     pub loan_id: u64,
-    pub total_repaid: u64,
+    pub principal_paid: u64,
     pub interest_paid: u64,
+    pub penalty_paid: u64,
+    pub total_paid: u64,
 }
 
 #[event]
 pub struct LoanRecovered {
+    pub pool: Pubkey,
     pub loan_id: u64,
+    pub keeper: Pubkey,
+    pub keeper_bounty: u64,
     pub admin_fee_distributed: u64,
     pub depositor_share: u64,
     pub treasury_share: u64,
+    pub junior_loss: u64,
+    pub senior_loss: u64,
+    pub guarantee_claimed: u64,
+}
+
+#[event]
+pub struct LoansBatchRecovered {
+    pub pool: Pubkey,
+    pub keeper: Pubkey,
+    pub recovered: u32,
+    pub skipped_not_due: u32,
+    pub skipped_needs_single: u32,
 }
 
 #[event]
 pub struct AuthorityTransferred {
+    pub pool: Pubkey,
+    pub loan_id: u64,
     pub program_pubkey: Pubkey,
     pub new_authority: Pubkey,
 }
 
 #[event]
 pub struct SolReclaimed {
+    pub pool: Pubkey,
     pub loan_id: u64,
     pub amount: u64,
     pub total_reclaimed: u64,
@@ -1062,14 +5604,30 @@ pub struct SolReclaimed {
 
 #[event]
 pub struct ProtocolPausedChanged {
+    pub pool: Pubkey,
     pub is_paused: bool,
 }
 
 #[event]
 pub struct ConfigUpdated {
+    pub pool: Pubkey,
     pub admin_fee_split_bps: u16,
     pub default_interest_rate_bps: u16,
     pub default_admin_fee_bps: u16,
+    pub senior_draw_bps: u16,
+    pub junior_yield_share_bps: u16,
+    pub auto_approve_threshold: u64,
+    pub approval_timeout: i64,
+    pub quote_signer: Pubkey,
+    pub borrower_allowlist_root: Option<[u8; 32]>,
+    pub extension_fee_bps: u16,
+    pub grace_period: i64,
+    pub penalty_interest_rate_bps: u16,
+    pub min_interest_period: i64,
+    pub min_interest_lamports: u64,
+    pub keeper_bounty_bps: u16,
+    pub redemption_window: i64,
+    pub recovery_penalty_bps: u16,
 }
 
 // ===== ERRORS =====
@@ -1112,6 +5670,78 @@ pub enum ErrorCode {
     ProgramAlreadySet,
     #[msg("Invalid program pubkey")]
     InvalidProgram,
+    #[msg("Tranche has been wiped out by losses")]
+    TrancheWipedOut,
+    #[msg("Account belongs to a different pool")]
+    PoolMismatch,
+    #[msg("Loan is not awaiting approval")]
+    LoanNotAwaitingApproval,
+    #[msg("Loan request has expired")]
+    LoanRequestExpired,
+    #[msg("Loan request has not expired yet")]
+    LoanRequestNotExpired,
+    #[msg("Signed quotes are not enabled for this pool")]
+    QuotesDisabled,
+    #[msg("Loan quote has expired")]
+    QuoteExpired,
+    #[msg("Loan terms do not match the signed quote")]
+    QuoteTermsMismatch,
+    #[msg("No valid ed25519 signature for the quote")]
+    MissingQuoteSignature,
+    #[msg("Quote nonce account is required")]
+    MissingQuoteNonce,
+    #[msg("Borrower is not on the pool allowlist")]
+    BorrowerNotAllowlisted,
+    #[msg("Repayment does not cover accrued interest")]
+    RepaymentBelowInterest,
+    #[msg("Loan has already expired")]
+    LoanExpired,
+    #[msg("Loan is still within its grace period")]
+    LoanInGracePeriod,
+    #[msg("Amount moved past the caller's limit")]
+    SlippageExceeded,
+    #[msg("Deployment cost has already been settled")]
+    DeploymentAlreadySettled,
+    #[msg("Account is not a writable loan of this pool")]
+    InvalidLoanAccount,
+    #[msg("Auction has ended")]
+    AuctionEnded,
+    #[msg("Auction is still running")]
+    AuctionStillRunning,
+    #[msg("Borrower can still redeem this loan")]
+    RedemptionWindowOpen,
+    #[msg("Redemption window has closed")]
+    RedemptionWindowClosed,
+    #[msg("Loan position has not been minted")]
+    PositionNotMinted,
+    #[msg("Loan position has been sold")]
+    PositionSold,
+    #[msg("Position token account does not belong to the position holder")]
+    InvalidPositionHolder,
+    #[msg("Collateral mint is not whitelisted")]
+    CollateralMintNotAllowed,
+    #[msg("Collateral accounts are required for this loan")]
+    MissingCollateralAccounts,
+    #[msg("Invalid collateral token account")]
+    InvalidCollateralAccount,
+    #[msg("Loan has no collateral to release")]
+    NoCollateral,
+    #[msg("Guarantor accounts are required for this loan")]
+    MissingGuarantor,
+    #[msg("Guarantor does not match the loan")]
+    InvalidGuarantor,
+    #[msg("Loan has no guarantee to release")]
+    NoGuarantee,
+    #[msg("Deployer is not active")]
+    DeployerInactive,
+    #[msg("No guarantee refund is due")]
+    NoGuaranteeRefund,
+    #[msg("Too many active deployers")]
+    TooManyDeployers,
+    #[msg("Position proceeds escrow is missing or wrong")]
+    MissingPositionProceeds,
+    #[msg("No position proceeds to claim")]
+    NoPositionProceeds,
 }
 
 #[cfg(test)]
@@ -1265,12 +5895,31 @@ mod tests {
     #[test]
     fn test_distribute_yield_zero_deposits() {
         let mut config = ProtocolConfig {
+            pool_id: 0,
             admin: Pubkey::default(),
             treasury: Pubkey::default(),
-            deployer: Pubkey::default(),
+            active_deployers: vec![],
+            next_deployer: 0,
             admin_fee_split_bps: 5000,
             default_interest_rate_bps: 500,
             default_admin_fee_bps: 100,
+            senior_draw_bps: 8000,
+            junior_yield_share_bps: 5000,
+            senior: TrancheState::default(),
+            junior: TrancheState::default(),
+            underwriter: Pubkey::default(),
+            auto_approve_threshold: u64::MAX,
+            approval_timeout: DEFAULT_APPROVAL_TIMEOUT,
+            quote_signer: Pubkey::default(),
+            borrower_allowlist_root: None,
+            extension_fee_bps: 0,
+            grace_period: 0,
+            penalty_interest_rate_bps: 0,
+            min_interest_period: 0,
+            min_interest_lamports: 0,
+            keeper_bounty_bps: 0,
+            redemption_window: 0,
+            recovery_penalty_bps: 0,
             total_deposits: 0,
             total_loans_outstanding: 0,
             total_yield_distributed: 0,
@@ -1289,12 +5938,31 @@ mod tests {
     #[test]
     fn test_distribute_yield_zero_amount() {
         let mut config = ProtocolConfig {
+            pool_id: 0,
             admin: Pubkey::default(),
             treasury: Pubkey::default(),
-            deployer: Pubkey::default(),
+            active_deployers: vec![],
+            next_deployer: 0,
             admin_fee_split_bps: 5000,
             default_interest_rate_bps: 500,
             default_admin_fee_bps: 100,
+            senior_draw_bps: 8000,
+            junior_yield_share_bps: 5000,
+            senior: TrancheState::default(),
+            junior: TrancheState::default(),
+            underwriter: Pubkey::default(),
+            auto_approve_threshold: u64::MAX,
+            approval_timeout: DEFAULT_APPROVAL_TIMEOUT,
+            quote_signer: Pubkey::default(),
+            borrower_allowlist_root: None,
+            extension_fee_bps: 0,
+            grace_period: 0,
+            penalty_interest_rate_bps: 0,
+            min_interest_period: 0,
+            min_interest_lamports: 0,
+            keeper_bounty_bps: 0,
+            redemption_window: 0,
+            recovery_penalty_bps: 0,
             total_deposits: 10_000_000_000,
             total_loans_outstanding: 0,
             total_yield_distributed: 0,
@@ -1313,12 +5981,31 @@ mod tests {
     #[test]
     fn test_distribute_yield_normal_case() {
         let mut config = ProtocolConfig {
+            pool_id: 0,
             admin: Pubkey::default(),
             treasury: Pubkey::default(),
-            deployer: Pubkey::default(),
+            active_deployers: vec![],
+            next_deployer: 0,
             admin_fee_split_bps: 5000,
             default_interest_rate_bps: 500,
             default_admin_fee_bps: 100,
+            senior_draw_bps: 8000,
+            junior_yield_share_bps: 5000,
+            senior: TrancheState::default(),
+            junior: TrancheState::default(),
+            underwriter: Pubkey::default(),
+            auto_approve_threshold: u64::MAX,
+            approval_timeout: DEFAULT_APPROVAL_TIMEOUT,
+            quote_signer: Pubkey::default(),
+            borrower_allowlist_root: None,
+            extension_fee_bps: 0,
+            grace_period: 0,
+            penalty_interest_rate_bps: 0,
+            min_interest_period: 0,
+            min_interest_lamports: 0,
+            keeper_bounty_bps: 0,
+            redemption_window: 0,
+            recovery_penalty_bps: 0,
             total_deposits: 10_000_000_000, // 10 SOL
             total_loans_outstanding: 5_000_000_000,
             total_yield_distributed: 0,
@@ -1336,12 +6023,31 @@ mod tests {
     #[test]
     fn test_distribute_yield_multiple_distributions() {
         let mut config = ProtocolConfig {
+            pool_id: 0,
             admin: Pubkey::default(),
             treasury: Pubkey::default(),
-            deployer: Pubkey::default(),
+            active_deployers: vec![],
+            next_deployer: 0,
             admin_fee_split_bps: 5000,
             default_interest_rate_bps: 500,
             default_admin_fee_bps: 100,
+            senior_draw_bps: 8000,
+            junior_yield_share_bps: 5000,
+            senior: TrancheState::default(),
+            junior: TrancheState::default(),
+            underwriter: Pubkey::default(),
+            auto_approve_threshold: u64::MAX,
+            approval_timeout: DEFAULT_APPROVAL_TIMEOUT,
+            quote_signer: Pubkey::default(),
+            borrower_allowlist_root: None,
+            extension_fee_bps: 0,
+            grace_period: 0,
+            penalty_interest_rate_bps: 0,
+            min_interest_period: 0,
+            min_interest_lamports: 0,
+            keeper_bounty_bps: 0,
+            redemption_window: 0,
+            recovery_penalty_bps: 0,
             total_deposits: 20_000_000_000, // 20 SOL
             total_loans_outstanding: 10_000_000_000,
             total_yield_distributed: 0,
@@ -1366,12 +6072,31 @@ mod tests {
     #[test]
     fn test_distribute_yield_large_amount() {
         let mut config = ProtocolConfig {
+            pool_id: 0,
             admin: Pubkey::default(),
             treasury: Pubkey::default(),
-            deployer: Pubkey::default(),
+            active_deployers: vec![],
+            next_deployer: 0,
             admin_fee_split_bps: 5000,
             default_interest_rate_bps: 500,
             default_admin_fee_bps: 100,
+            senior_draw_bps: 8000,
+            junior_yield_share_bps: 5000,
+            senior: TrancheState::default(),
+            junior: TrancheState::default(),
+            underwriter: Pubkey::default(),
+            auto_approve_threshold: u64::MAX,
+            approval_timeout: DEFAULT_APPROVAL_TIMEOUT,
+            quote_signer: Pubkey::default(),
+            borrower_allowlist_root: None,
+            extension_fee_bps: 0,
+            grace_period: 0,
+            penalty_interest_rate_bps: 0,
+            min_interest_period: 0,
+            min_interest_lamports: 0,
+            keeper_bounty_bps: 0,
+            redemption_window: 0,
+            recovery_penalty_bps: 0,
             total_deposits: 100_000_000_000, // 100 SOL
             total_loans_outstanding: 50_000_000_000,
             total_yield_distributed: 0,
@@ -1389,12 +6114,31 @@ mod tests {
     #[test]
     fn test_distribute_yield_small_deposits_large_yield() {
         let mut config = ProtocolConfig {
+            pool_id: 0,
             admin: Pubkey::default(),
             treasury: Pubkey::default(),
-            deployer: Pubkey::default(),
+            active_deployers: vec![],
+            next_deployer: 0,
             admin_fee_split_bps: 5000,
             default_interest_rate_bps: 500,
             default_admin_fee_bps: 100,
+            senior_draw_bps: 8000,
+            junior_yield_share_bps: 5000,
+            senior: TrancheState::default(),
+            junior: TrancheState::default(),
+            underwriter: Pubkey::default(),
+            auto_approve_threshold: u64::MAX,
+            approval_timeout: DEFAULT_APPROVAL_TIMEOUT,
+            quote_signer: Pubkey::default(),
+            borrower_allowlist_root: None,
+            extension_fee_bps: 0,
+            grace_period: 0,
+            penalty_interest_rate_bps: 0,
+            min_interest_period: 0,
+            min_interest_lamports: 0,
+            keeper_bounty_bps: 0,
+            redemption_window: 0,
+            recovery_penalty_bps: 0,
             total_deposits: 1_000_000, // 0.001 SOL
             total_loans_outstanding: 0,
             total_yield_distributed: 0,
@@ -1410,6 +6154,648 @@ mod tests {
         assert_eq!(config.total_yield_distributed, yield_amount);
     }
 
+    // ===== PARTIAL REPAYMENT TESTS =====
+
+    #[test]
+    fn test_split_repayment_pro_rata() {
+        assert_eq!(split_repayment(100, 800, 200), (80, 20));
+        assert_eq!(split_repayment(0, 800, 200), (0, 0));
+    }
+
+    #[test]
+    fn test_split_repayment_rounding_dust_to_junior() {
+        // Rounding leftovers land on the junior side and never exceed its balance
+        assert_eq!(split_repayment(1, 1, 1), (0, 1));
+        let (senior, junior) = split_repayment(999, 2, 1_000);
+        assert_eq!(senior + junior, 999);
+        assert!(junior <= 1_000);
+    }
+
+    #[test]
+    fn test_split_repayment_full_payoff() {
+        assert_eq!(split_repayment(1_000, 800, 200), (800, 200));
+        assert_eq!(split_repayment(5_000, 800, 200), (800, 200));
+    }
+
+    #[test]
+    fn test_split_fee() {
+        assert_eq!(split_fee(1_000_000, 5000), (500_000, 500_000));
+        assert_eq!(split_fee(1_000_000, 10000), (1_000_000, 0));
+        assert_eq!(split_fee(999, 5000), (499, 500));
+        assert_eq!(split_fee(0, 5000), (0, 0));
+    }
+
+    // ===== MINIMUM INTEREST TESTS =====
+
+    #[test]
+    fn test_minimum_interest_top_up() {
+        // Repaid early: charged up to the minimum
+        assert_eq!(minimum_interest_top_up(1_000, 0, 10), 990);
+        // Earlier partial payments count towards the minimum
+        assert_eq!(minimum_interest_top_up(1_000, 600, 100), 300);
+        // Minimum already met
+        assert_eq!(minimum_interest_top_up(1_000, 600, 500), 0);
+        assert_eq!(minimum_interest_top_up(0, 0, 0), 0);
+    }
+
+    // ===== PROGRAM REPAYMENT TESTS =====
+
+    #[test]
+    fn test_repayer_address_is_per_program() {
+        let program_a = Pubkey::new_unique();
+        let program_b = Pubkey::new_unique();
+
+        let (repayer_a, bump_a) = repayer_address(&program_a);
+        assert_eq!(
+            Pubkey::create_program_address(&[REPAYER_SEED, &[bump_a]], &program_a).unwrap(),
+            repayer_a
+        );
+        assert_ne!(repayer_a, repayer_address(&program_b).0);
+    }
+
+    // ===== PROGRAM UPGRADE TESTS =====
+
+    #[test]
+    fn test_programdata_hash_skips_metadata() {
+        let header = UpgradeableLoaderState::size_of_programdata_metadata();
+        let mut a = vec![1u8; header + 64];
+        let mut b = vec![2u8; header + 64];
+        b[header..].copy_from_slice(&a[header..]);
+        // Same code under a different header hashes the same
+        assert_eq!(programdata_hash(&a), programdata_hash(&b));
+
+        a[header] = 0xff;
+        assert_ne!(programdata_hash(&a), programdata_hash(&b));
+        assert_eq!(programdata_hash(&a[..4]), hashv(&[&[]]).to_bytes());
+    }
+
+    // ===== AUCTION TESTS =====
+
+    #[test]
+    fn test_auction_price_decays_to_floor() {
+        let start = 10_000_000_000;
+        let floor = 2_000_000_000;
+
+        assert_eq!(auction_price(start, floor, 1_000, 800, 1_000), start);
+        assert_eq!(auction_price(start, floor, 1_000, 800, 1_400), 6_000_000_000);
+        assert_eq!(auction_price(start, floor, 1_000, 800, 1_800), floor);
+        // Holds at the floor after the decay period, and at the start price before it
+        assert_eq!(auction_price(start, floor, 1_000, 800, 5_000), floor);
+        assert_eq!(auction_price(start, floor, 1_000, 800, 0), start);
+        // Flat auction
+        assert_eq!(auction_price(floor, floor, 1_000, 800, 1_400), floor);
+    }
+
+    // ===== LOAN POSITION TESTS =====
+
+    #[test]
+    fn test_holds_position() {
+        let mint = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let mut loan = sample_loan(800, 200, 0);
+
+        assert!(!holds_position(&loan, &mint, 1, &holder, &holder));
+        loan.position_mint = Some(mint);
+        assert!(holds_position(&loan, &mint, 1, &holder, &holder));
+        assert!(!holds_position(&loan, &mint, 0, &holder, &holder));
+        assert!(!holds_position(&loan, &Pubkey::new_unique(), 1, &holder, &holder));
+        assert!(!holds_position(&loan, &mint, 1, &holder, &Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_sell_position_moves_repayments_off_pool_books() {
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+        config.senior.loans_outstanding = 800_000_000;
+        config.junior.loans_outstanding = 200_000_000;
+        config.total_loans_outstanding = 1_000_000_000;
+        let mut loan = sample_loan(800_000_000, 200_000_000, 0);
+
+        let (price, interest) = position_price(&config, &loan, SECONDS_PER_YEAR as i64);
+        assert_eq!(interest, 50_000_000);
+        assert_eq!(price, 1_050_000_000);
+
+        sell_position(&mut config, &mut loan, interest);
+        assert!(loan.position_sold);
+        assert_eq!(config.senior.loans_outstanding, 0);
+        assert_eq!(config.junior.loans_outstanding, 0);
+        assert_eq!(config.total_loans_outstanding, 0);
+        assert_eq!(config.total_deposits, 10_050_000_000);
+
+        // The holder's repayment leaves the pool untouched
+        let repaid = apply_repayment(&mut config, &mut loan, interest, 0, 1_000_000_000, SECONDS_PER_YEAR as i64);
+        assert!(repaid);
+        assert_eq!(loan.principal_outstanding, 0);
+        assert_eq!(config.total_deposits, 10_050_000_000);
+        assert_eq!(config.total_loans_outstanding, 0);
+    }
+
+    // ===== COLLATERAL TESTS =====
+
+    #[test]
+    fn test_required_collateral() {
+        // 150% of 1 SOL in a 6 decimal token worth 0.01 SOL: 150 tokens
+        assert_eq!(required_collateral(1_000_000_000, 15000, 10_000_000, 6), Some(150_000_000));
+        // Rounds up to the next base unit
+        assert_eq!(required_collateral(1, 10000, 3, 0), Some(1));
+        assert_eq!(required_collateral(1_000_000_000, 10000, 0, 6), None);
+        assert_eq!(required_collateral(u64::MAX, 60000, 1, 9), None);
+    }
+
+    // ===== GRACE PERIOD TESTS =====
+
+    #[test]
+    fn test_split_accrual_period() {
+        // Before expiry nothing is overdue
+        assert_eq!(split_accrual_period(100, 1_000, 600), (500, 0));
+        // Straddling expiry
+        assert_eq!(split_accrual_period(100, 1_000, 1_500), (1_400, 500));
+        // Last accrual already past expiry
+        assert_eq!(split_accrual_period(1_200, 1_000, 1_500), (300, 300));
+        // Clock behind the last accrual
+        assert_eq!(split_accrual_period(1_500, 1_000, 1_200), (0, 0));
+    }
+
+    #[test]
+    fn test_calculate_penalty_interest() {
+        let principal = 1_000_000_000;
+        // 20% penalty on a 5% loan charges the extra 15%
+        assert_eq!(
+            calculate_penalty_interest(principal, 500, 2000, SECONDS_PER_YEAR),
+            150_000_000
+        );
+        // Penalty rate at or below the loan rate charges nothing extra
+        assert_eq!(calculate_penalty_interest(principal, 500, 500, SECONDS_PER_YEAR), 0);
+        assert_eq!(calculate_penalty_interest(principal, 500, 0, SECONDS_PER_YEAR), 0);
+        assert_eq!(calculate_penalty_interest(principal, 500, 2000, 0), 0);
+    }
+
+    // ===== TRANCHE TESTS =====
+
+    fn tranche_config(senior_deposits: u64, junior_deposits: u64) -> ProtocolConfig {
+        ProtocolConfig {
+            pool_id: 0,
+            admin: Pubkey::default(),
+            treasury: Pubkey::default(),
+            active_deployers: vec![],
+            next_deployer: 0,
+            admin_fee_split_bps: 5000,
+            default_interest_rate_bps: 500,
+            default_admin_fee_bps: 100,
+            senior_draw_bps: 8000,
+            junior_yield_share_bps: 7000,
+            senior: TrancheState {
+                total_deposits: senior_deposits,
+                total_shares: senior_deposits,
+                loans_outstanding: 0,
+            },
+            junior: TrancheState {
+                total_deposits: junior_deposits,
+                total_shares: junior_deposits,
+                loans_outstanding: 0,
+            },
+            underwriter: Pubkey::default(),
+            auto_approve_threshold: u64::MAX,
+            approval_timeout: DEFAULT_APPROVAL_TIMEOUT,
+            quote_signer: Pubkey::default(),
+            borrower_allowlist_root: None,
+            extension_fee_bps: 0,
+            grace_period: 0,
+            penalty_interest_rate_bps: 0,
+            min_interest_period: 0,
+            min_interest_lamports: 0,
+            keeper_bounty_bps: 0,
+            redemption_window: 0,
+            recovery_penalty_bps: 0,
+            total_deposits: senior_deposits + junior_deposits,
+            total_loans_outstanding: 0,
+            total_yield_distributed: 0,
+            loan_counter: 0,
+            is_paused: false,
+            bump: 0,
+        }
+    }
+
+    fn sample_loan(senior_principal: u64, junior_principal: u64, admin_fee_paid: u64) -> Loan {
+        Loan {
+            loan_id: 0,
+            pool: Pubkey::default(),
+            borrower: Pubkey::default(),
+            deployer: Pubkey::default(),
+            program_pubkey: Pubkey::default(),
+            principal: senior_principal + junior_principal,
+            principal_outstanding: senior_principal + junior_principal,
+            senior_principal,
+            junior_principal,
+            senior_loss: 0,
+            duration: 30 * 24 * 60 * 60,
+            interest_rate_bps: 500,
+            admin_fee_bps: 100,
+            admin_fee_paid,
+            extension_count: 0,
+            requested_ts: 0,
+            start_ts: 0,
+            last_accrual_ts: 0,
+            state: LoanState::Active,
+            authority_pda: Pubkey::default(),
+            repaid_ts: Some(0),
+            recovered_ts: Some(0),
+            interest_paid: Some(0),
+            reclaimed_amount: Some(0),
+            reclaimed_ts: Some(0),
+            deployment_cost: None,
+            position_mint: None,
+            position_sold: false,
+            collateral_mint: None,
+            collateral_amount: 0,
+            collateral_owner: Pubkey::default(),
+            guarantor: None,
+            guarantee_amount: 0,
+            guarantee_claimed: 0,
+            guarantee_refund_due: 0,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn test_write_off_loan_pays_keeper_bounty() {
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+        config.keeper_bounty_bps = 1000;
+        config.senior.loans_outstanding = 800_000_000;
+        config.junior.loans_outstanding = 200_000_000;
+        let mut loan = sample_loan(800_000_000, 200_000_000, 10_000_000);
+
+        let outcome = write_off_loan(&mut config, &mut loan, 100);
+
+        // 10% bounty, the rest split 50/50
+        assert_eq!(outcome.keeper_bounty, 1_000_000);
+        assert_eq!(outcome.depositor_share, 4_500_000);
+        assert_eq!(outcome.treasury_share, 4_500_000);
+        assert_eq!(outcome.principal_written_off, 1_000_000_000);
+        assert_eq!(outcome.junior_loss, 1_000_000_000);
+        assert_eq!(outcome.senior_loss, 0);
+
+        assert_eq!(loan.state, LoanState::Recovered);
+        assert_eq!(loan.recovered_ts, Some(100));
+        assert_eq!(config.senior.loans_outstanding, 0);
+        assert_eq!(config.junior.loans_outstanding, 0);
+    }
+
+    #[test]
+    fn test_write_off_loan_claims_guarantee_first() {
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+        config.senior.loans_outstanding = 800_000_000;
+        config.junior.loans_outstanding = 200_000_000;
+        let mut loan = sample_loan(800_000_000, 200_000_000, 0);
+        loan.guarantee_amount = 300_000_000;
+
+        let outcome = write_off_loan(&mut config, &mut loan, 100);
+        assert_eq!(outcome.guarantee_claimed, 300_000_000);
+        assert_eq!(outcome.junior_loss, 700_000_000);
+        assert_eq!(outcome.senior_loss, 0);
+
+        // A guarantee larger than the shortfall covers it all
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+        let mut loan = sample_loan(0, 100_000_000, 0);
+        loan.guarantee_amount = 300_000_000;
+        config.junior.loans_outstanding = 100_000_000;
+
+        let outcome = write_off_loan(&mut config, &mut loan, 100);
+        assert_eq!(outcome.guarantee_claimed, 100_000_000);
+        assert_eq!(outcome.junior_loss, 0);
+        assert_eq!(config.junior.total_deposits, 2_000_000_000);
+    }
+
+    #[test]
+    fn test_recovery_keeps_vault_backing_deposits() {
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+        config.keeper_bounty_bps = 1000;
+        config.senior.loans_outstanding = 800_000_000;
+        config.junior.loans_outstanding = 200_000_000;
+        config.total_loans_outstanding = 1_000_000_000;
+        let mut vault = config.total_deposits - config.total_loans_outstanding;
+        let mut loan = sample_loan(800_000_000, 200_000_000, 10_000_000);
+        loan.guarantee_amount = 300_000_000;
+
+        let outcome = write_off_loan(&mut config, &mut loan, 100);
+        config.total_loans_outstanding -= outcome.principal_written_off;
+        // What recover_loan moves into the vault
+        vault += outcome.depositor_share + outcome.guarantee_claimed;
+
+        assert_eq!(vault, config.total_deposits - config.total_loans_outstanding);
+    }
+
+    #[test]
+    fn test_is_recoverable() {
+        let mut loan = sample_loan(800, 200, 0);
+        let expiry = loan.start_ts + loan.duration;
+
+        assert!(!is_recoverable(&loan, 0, expiry - 1));
+        assert!(is_recoverable(&loan, 0, expiry));
+        // Grace period pushes eligibility back
+        assert!(!is_recoverable(&loan, 3600, expiry + 3599));
+        assert!(is_recoverable(&loan, 3600, expiry + 3600));
+
+        loan.state = LoanState::Pending;
+        assert!(is_recoverable(&loan, 0, expiry));
+        loan.state = LoanState::Recovered;
+        assert!(!is_recoverable(&loan, 0, expiry));
+        loan.state = LoanState::RepaidPendingTransfer;
+        assert!(!is_recoverable(&loan, 0, expiry));
+    }
+
+    #[test]
+    fn test_in_redemption_window() {
+        let mut loan = sample_loan(800, 200, 0);
+        assert!(!in_redemption_window(&loan, 3600, 0));
+
+        loan.state = LoanState::Recovered;
+        loan.recovered_ts = Some(1_000);
+        assert!(in_redemption_window(&loan, 3600, 1_000));
+        assert!(in_redemption_window(&loan, 3600, 4_600));
+        assert!(!in_redemption_window(&loan, 3600, 4_601));
+        // No window configured: only the recovery slot itself
+        assert!(!in_redemption_window(&loan, 0, 1_001));
+    }
+
+    #[test]
+    fn test_collateral_kept_for_redemption() {
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+        config.senior.loans_outstanding = 800_000_000;
+        config.junior.loans_outstanding = 200_000_000;
+        let mut loan = sample_loan(800_000_000, 200_000_000, 0);
+        loan.collateral_mint = Some(Pubkey::new_unique());
+        loan.collateral_amount = 150_000_000;
+
+        // Recovery leaves the collateral in escrow, and it cannot be released
+        write_off_loan(&mut config, &mut loan, 1_000);
+        assert_eq!(loan.collateral_amount, 150_000_000);
+        assert!(!collateral_releasable(&loan));
+        assert!(in_redemption_window(&loan, 3600, 4_600));
+
+        // Redeeming buys it back along with the program
+        loan.state = LoanState::Redeemed;
+        assert!(collateral_releasable(&loan));
+    }
+
+    #[test]
+    fn test_collateral_stays_with_poster_after_transfer() {
+        let mut loan = sample_loan(800_000_000, 200_000_000, 0);
+        let poster = Pubkey::new_unique();
+        loan.borrower = poster;
+        loan.collateral_mint = Some(Pubkey::new_unique());
+        loan.collateral_amount = 150_000_000;
+        loan.collateral_owner = poster;
+        assert!(returns_collateral_on_repay(&loan));
+
+        // The new borrower's repayment leaves it for release_collateral
+        loan.borrower = Pubkey::new_unique();
+        assert!(!returns_collateral_on_repay(&loan));
+        assert_eq!(loan.collateral_owner, poster);
+    }
+
+    #[test]
+    fn test_book_recovery_refunds_guarantor_first() {
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+        let mut loan = sample_loan(0, 0, 0);
+        loan.guarantee_claimed = 300_000_000;
+        loan.senior_loss = 100_000_000;
+
+        // Recovered SOL first wins back what the guarantee paid
+        book_recovery(&mut config, &mut loan, 200_000_000);
+        assert_eq!(loan.guarantee_claimed, 100_000_000);
+        assert_eq!(loan.guarantee_refund_due, 200_000_000);
+        assert_eq!(config.total_deposits, 10_000_000_000);
+
+        // Only the rest restores depositors, seniors first
+        book_recovery(&mut config, &mut loan, 250_000_000);
+        assert_eq!(loan.guarantee_claimed, 0);
+        assert_eq!(loan.guarantee_refund_due, 300_000_000);
+        assert_eq!(loan.senior_loss, 0);
+        assert_eq!(config.senior.total_deposits, 8_100_000_000);
+        assert_eq!(config.junior.total_deposits, 2_050_000_000);
+        assert_eq!(config.total_deposits, 10_150_000_000);
+    }
+
+    #[test]
+    fn test_deployers_assigned_in_turn() {
+        let mut config = tranche_config(0, 0);
+        let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(config.assigned_deployer(), None);
+
+        for deployer in [a, b, c] {
+            config.set_deployer_active(deployer, true).unwrap();
+        }
+        // Registering twice does not add a second turn
+        config.set_deployer_active(a, true).unwrap();
+        assert_eq!(config.active_deployers.len(), 3);
+
+        let mut assigned = vec![];
+        for _ in 0..4 {
+            assigned.push(config.assigned_deployer().unwrap());
+            config.advance_deployer();
+        }
+        assert_eq!(assigned, vec![a, b, c, a]);
+
+        // Deactivating the last in line wraps the rotation back to the start
+        config.advance_deployer();
+        assert_eq!(config.assigned_deployer(), Some(c));
+        config.set_deployer_active(c, false).unwrap();
+        assert_eq!(config.assigned_deployer(), Some(a));
+
+        config.set_deployer_active(a, false).unwrap();
+        config.set_deployer_active(b, false).unwrap();
+        assert_eq!(config.assigned_deployer(), None);
+        config.advance_deployer();
+    }
+
+    #[test]
+    fn test_split_principal() {
+        assert_eq!(split_principal(1_000_000_000, 8000), (800_000_000, 200_000_000));
+        assert_eq!(split_principal(1_000_000_000, 10000), (1_000_000_000, 0));
+        assert_eq!(split_principal(1_000_000_000, 0), (0, 1_000_000_000));
+        // Rounding dust goes to the junior draw
+        assert_eq!(split_principal(3, 5000), (1, 2));
+    }
+
+    #[test]
+    fn test_distribute_yield_favours_juniors() {
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+
+        distribute_yield(&mut config, 100_000_000);
+
+        assert_eq!(config.junior.total_deposits, 2_070_000_000);
+        assert_eq!(config.senior.total_deposits, 8_030_000_000);
+        assert_eq!(config.total_deposits, 10_100_000_000);
+        assert_eq!(config.total_yield_distributed, 100_000_000);
+    }
+
+    #[test]
+    fn test_distribute_yield_empty_junior_tranche() {
+        let mut config = tranche_config(8_000_000_000, 0);
+
+        distribute_yield(&mut config, 100_000_000);
+
+        assert_eq!(config.junior.total_deposits, 0);
+        assert_eq!(config.senior.total_deposits, 8_100_000_000);
+    }
+
+    #[test]
+    fn test_absorb_loss_junior_first() {
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+
+        let (junior_loss, senior_loss) = absorb_loss(&mut config, 1_500_000_000);
+
+        assert_eq!((junior_loss, senior_loss), (1_500_000_000, 0));
+        assert_eq!(config.junior.total_deposits, 500_000_000);
+        assert_eq!(config.senior.total_deposits, 8_000_000_000);
+        assert_eq!(config.total_deposits, 8_500_000_000);
+    }
+
+    #[test]
+    fn test_absorb_loss_spills_into_senior() {
+        let mut config = tranche_config(8_000_000_000, 2_000_000_000);
+
+        let (junior_loss, senior_loss) = absorb_loss(&mut config, 3_000_000_000);
+
+        assert_eq!((junior_loss, senior_loss), (2_000_000_000, 1_000_000_000));
+        assert_eq!(config.junior.total_deposits, 0);
+        assert_eq!(config.senior.total_deposits, 7_000_000_000);
+        assert_eq!(config.total_deposits, 7_000_000_000);
+    }
+
+    #[test]
+    fn test_credit_recovery_restores_senior_first() {
+        let mut config = tranche_config(7_000_000_000, 0);
+
+        let senior_credit = credit_recovery(&mut config, 1_000_000_000, 1_500_000_000);
+
+        assert_eq!(senior_credit, 1_000_000_000);
+        assert_eq!(config.senior.total_deposits, 8_000_000_000);
+        assert_eq!(config.junior.total_deposits, 500_000_000);
+        assert_eq!(config.total_deposits, 8_500_000_000);
+    }
+
+    #[test]
+    fn test_tranche_share_pricing() {
+        let mut tranche = TrancheState::default();
+        assert_eq!(tranche.shares_for_deposit(1_000), Some(1_000));
+
+        tranche.total_deposits = 1_000;
+        tranche.total_shares = 1_000;
+        tranche.total_deposits += 1_000; // yield doubles the value per share
+
+        assert_eq!(tranche.shares_for_deposit(1_000), Some(500));
+        assert_eq!(tranche.lamports_for_shares(500), 1_000);
+
+        tranche.total_deposits = 0; // wiped out by losses
+        assert_eq!(tranche.shares_for_deposit(1_000), None);
+        assert_eq!(tranche.lamports_for_shares(500), 0);
+    }
+
+    #[test]
+    fn test_tranche_available_liquidity() {
+        let tranche = TrancheState {
+            total_deposits: 1_000,
+            total_shares: 1_000,
+            loans_outstanding: 1_200,
+        };
+        assert_eq!(tranche.available_liquidity(), 0);
+    }
+
+    // ===== QUOTE SIGNATURE TESTS =====
+
+    /// Builds ed25519 program instruction data the way the web3.js helper does:
+    /// offsets, then public key, signature and message in the same instruction
+    fn ed25519_ix_data(signer: &Pubkey, message: &[u8]) -> Vec<u8> {
+        let public_key_offset: u16 = 16;
+        let signature_offset: u16 = public_key_offset + 32;
+        let message_offset: u16 = signature_offset + 64;
+
+        let mut data = vec![1u8, 0];
+        for value in [
+            signature_offset,
+            u16::MAX,
+            public_key_offset,
+            u16::MAX,
+            message_offset,
+            message.len() as u16,
+            u16::MAX,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&signer.to_bytes());
+        data.extend_from_slice(&[7u8; 64]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    #[test]
+    fn test_ed25519_instruction_matches() {
+        let signer = Pubkey::new_unique();
+        let message = b"quote".to_vec();
+        let data = ed25519_ix_data(&signer, &message);
+
+        assert!(ed25519_instruction_matches(&data, &signer, &message));
+        assert!(!ed25519_instruction_matches(&data, &Pubkey::new_unique(), &message));
+        assert!(!ed25519_instruction_matches(&data, &signer, b"other"));
+    }
+
+    #[test]
+    fn test_ed25519_instruction_rejects_external_offsets() {
+        let signer = Pubkey::new_unique();
+        let message = b"quote".to_vec();
+        let mut data = ed25519_ix_data(&signer, &message);
+
+        // Point the message at another instruction
+        data[14..16].copy_from_slice(&0u16.to_le_bytes());
+        assert!(!ed25519_instruction_matches(&data, &signer, &message));
+    }
+
+    #[test]
+    fn test_ed25519_instruction_rejects_truncated_data() {
+        let signer = Pubkey::new_unique();
+        let message = b"quote".to_vec();
+        let data = ed25519_ix_data(&signer, &message);
+
+        assert!(!ed25519_instruction_matches(&data[..data.len() - 1], &signer, &message));
+        assert!(!ed25519_instruction_matches(&data[..8], &signer, &message));
+    }
+
+    // ===== ALLOWLIST TESTS =====
+
+    fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+        if a <= b {
+            hashv(&[a, b]).to_bytes()
+        } else {
+            hashv(&[b, a]).to_bytes()
+        }
+    }
+
+    #[test]
+    fn test_verify_merkle_proof() {
+        let borrowers: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let leaves: Vec<[u8; 32]> = borrowers
+            .iter()
+            .map(|b| hashv(&[b.as_ref()]).to_bytes())
+            .collect();
+        let left = hash_pair(&leaves[0], &leaves[1]);
+        let right = hash_pair(&leaves[2], &leaves[3]);
+        let root = hash_pair(&left, &right);
+
+        assert!(verify_merkle_proof(&[leaves[1], right], &root, &borrowers[0]));
+        assert!(verify_merkle_proof(&[leaves[2], left], &root, &borrowers[3]));
+        assert!(!verify_merkle_proof(&[leaves[1], right], &root, &borrowers[2]));
+        assert!(!verify_merkle_proof(&[], &root, &Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_verify_merkle_proof_single_leaf() {
+        let borrower = Pubkey::new_unique();
+        let root = hashv(&[borrower.as_ref()]).to_bytes();
+
+        assert!(verify_merkle_proof(&[], &root, &borrower));
+    }
+
     // ===== EDGE CASE TESTS =====
 
     #[test]
@@ -1458,9 +6844,35 @@ mod tests {
         assert!(DepositorRecord::SIZE >= 32 + 8 * 3);
     }
 
+    #[test]
+    fn test_deployer_record_accounting() {
+        let mut record = DeployerRecord {
+            pool: Pubkey::default(),
+            deployer: Pubkey::default(),
+            active: true,
+            lamports_received: 0,
+            lamports_returned: 0,
+            loans_in_flight: 0,
+            loans_failed: 0,
+            registered_ts: 0,
+            bump: 0,
+        };
+
+        record.record_disbursement(2_000_000_000);
+        record.record_disbursement(1_000_000_000);
+        assert_eq!(record.loans_in_flight, 2);
+        assert_eq!(record.lamports_held(), 3_000_000_000);
+
+        record.record_failure();
+        record.lamports_returned += 500_000_000;
+        assert_eq!(record.loans_in_flight, 1);
+        assert_eq!(record.loans_failed, 1);
+        assert_eq!(record.lamports_held(), 2_500_000_000);
+    }
+
     #[test]
     fn test_loan_size() {
         // Verify the SIZE constant matches actual struct size requirements
-        assert!(Loan::SIZE >= 32 * 3 + 8 * 4 + 2 * 2 + 1 + 9 * 5);
+        assert!(Loan::SIZE >= 32 * 4 + 8 * 4 + 2 * 2 + 1 + 9 * 6 + 33 + 1 + 33 + 8 + 32 + 33 + 8 + 8 + 8);
     }
 }
\ No newline at end of file
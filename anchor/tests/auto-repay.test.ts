@@ -0,0 +1,235 @@
+import * as anchor from '@coral-xyz/anchor';
+import { Program } from '@coral-xyz/anchor';
+import { Solignition } from "../target/types/solignition";
+import { AutoRepay } from "../target/types/auto_repay";
+import {
+  PublicKey,
+  SystemProgram,
+  Keypair,
+  LAMPORTS_PER_SOL,
+  Transaction,
+  TransactionInstruction,
+} from "@solana/web3.js";
+import { assert } from "chai";
+
+describe("auto_repay", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Solignition as Program<Solignition>;
+  const autoRepay = anchor.workspace.AutoRepay as Program<AutoRepay>;
+  const connection = provider.connection;
+
+  const VAULT_SEED = Buffer.from("vault");
+  const AUTHORITY_SEED = Buffer.from("authority");
+  const ADMIN_SEED = Buffer.from("admin");
+  const TREASURY_SEED = Buffer.from("treasury");
+  const LOAN_SEED = Buffer.from("loan");
+  const PROTOCOL_CONFIG_SEED = Buffer.from("config");
+  const REPAYER_SEED = Buffer.from("repayer");
+  const EVENT_AUTHORITY_SEED = Buffer.from("__event_authority");
+  const BPF_LOADER_UPGRADEABLE = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+
+  let admin: Keypair;
+  let borrower: Keypair;
+  let deployer: Keypair;
+
+  // A fresh pool so this suite doesn't depend on other tests
+  const poolId = new anchor.BN(Date.now());
+  const loanId = new anchor.BN(0);
+
+  let protocolConfigPda: PublicKey;
+  let vaultPda: PublicKey;
+  let authorityPda: PublicKey;
+  let adminPda: PublicKey;
+  let treasuryPda: PublicKey;
+  let loanPda: PublicKey;
+  let repayerPda: PublicKey;
+  let eventAuthorityPda: PublicKey;
+
+  const repayAccounts = () => ({
+    repayer: repayerPda,
+    loan: loanPda,
+    protocolConfig: protocolConfigPda,
+    vault: vaultPda,
+    treasury: treasuryPda,
+    eventAuthority: eventAuthorityPda,
+    solignitionProgram: program.programId,
+    systemProgram: SystemProgram.programId,
+  });
+
+  before(async () => {
+    admin = Keypair.generate();
+    borrower = Keypair.generate();
+    deployer = Keypair.generate();
+
+    const airdropAmount = 100 * LAMPORTS_PER_SOL;
+    for (const kp of [admin, borrower]) {
+      await connection.confirmTransaction(
+        await connection.requestAirdrop(kp.publicKey, airdropAmount)
+      );
+    }
+
+    [protocolConfigPda] = PublicKey.findProgramAddressSync(
+      [PROTOCOL_CONFIG_SEED, poolId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const pool = protocolConfigPda.toBuffer();
+    [vaultPda] = PublicKey.findProgramAddressSync([VAULT_SEED, pool], program.programId);
+    [authorityPda] = PublicKey.findProgramAddressSync([AUTHORITY_SEED, pool], program.programId);
+    [adminPda] = PublicKey.findProgramAddressSync([ADMIN_SEED, pool], program.programId);
+    [treasuryPda] = PublicKey.findProgramAddressSync([TREASURY_SEED, pool], program.programId);
+    [loanPda] = PublicKey.findProgramAddressSync(
+      [LOAN_SEED, pool, loanId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    [eventAuthorityPda] = PublicKey.findProgramAddressSync([EVENT_AUTHORITY_SEED], program.programId);
+
+    // The example program's repayer PDA, derived under its own program id
+    [repayerPda] = PublicKey.findProgramAddressSync([REPAYER_SEED], autoRepay.programId);
+
+    await program.methods
+      .initialize(poolId, 5000, 500, 100, 10000, 0)
+      .accountsPartial({
+        admin: admin.publicKey,
+        protocolConfig: protocolConfigPda,
+        vault: vaultPda,
+        authorityPda: authorityPda,
+        adminPda: adminPda,
+        treasury: treasuryPda,
+        deployer: deployer.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    await program.methods
+      .deposit({ senior: {} }, new anchor.BN(10 * LAMPORTS_PER_SOL))
+      .accountsPartial({
+        depositor: admin.publicKey,
+        protocolConfig: protocolConfigPda,
+        vault: vaultPda,
+      })
+      .signers([admin])
+      .rpc();
+
+    await program.methods
+      .requestLoan(new anchor.BN(2 * LAMPORTS_PER_SOL), new anchor.BN(30 * 24 * 60 * 60), 500, 100, null, [], new anchor.BN(0))
+      .accountsPartial({
+        borrower: borrower.publicKey,
+        loan: loanPda,
+        protocolConfig: protocolConfigPda,
+        vault: vaultPda,
+        adminPda: adminPda,
+        deployer: deployer.publicKey,
+        quoteNonce: null,
+        instructions: null,
+        collateralConfig: null,
+        collateralMint: null,
+        borrowerCollateral: null,
+        loanCollateral: null,
+        tokenProgram: null,
+        guarantor: null,
+        guaranteeEscrow: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([borrower])
+      .rpc();
+
+    // The pool's authority PDA must hold the program's upgrade authority
+    const [programDataPda] = PublicKey.findProgramAddressSync(
+      [autoRepay.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE
+    );
+    const setAuthorityData = Buffer.alloc(4);
+    setAuthorityData.writeUInt32LE(4); // SetAuthority
+    await provider.sendAndConfirm(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: BPF_LOADER_UPGRADEABLE,
+          keys: [
+            { pubkey: programDataPda, isSigner: false, isWritable: true },
+            { pubkey: provider.wallet.publicKey, isSigner: true, isWritable: false },
+            { pubkey: authorityPda, isSigner: false, isWritable: false },
+          ],
+          data: setAuthorityData,
+        })
+      )
+    );
+
+    // Record the example program as the one this loan financed
+    await program.methods
+      .setDeployedProgram(loanId, autoRepay.programId)
+      .accountsPartial({
+        admin: admin.publicKey,
+        protocolConfig: protocolConfigPda,
+        loan: loanPda,
+      })
+      .signers([admin])
+      .rpc();
+
+    // Protocol revenue collected by the program
+    await provider.sendAndConfirm(
+      new Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: admin.publicKey,
+          toPubkey: repayerPda,
+          lamports: 3 * LAMPORTS_PER_SOL,
+        })
+      ),
+      [admin]
+    );
+  });
+
+  it("should let the financed program pay down its own loan", async () => {
+    const amount = new anchor.BN(LAMPORTS_PER_SOL);
+    const before = await program.account.loan.fetch(loanPda);
+    const repayerBefore = await connection.getBalance(repayerPda);
+
+    await autoRepay.methods
+      .repay(loanId, amount)
+      .accountsPartial(repayAccounts())
+      .rpc();
+
+    const after = await program.account.loan.fetch(loanPda);
+    const repayerAfter = await connection.getBalance(repayerPda);
+
+    assert.ok(after.principalOutstanding.lt(before.principalOutstanding));
+    assert.equal(repayerBefore - repayerAfter, amount.toNumber());
+    assert.ok(after.borrower.equals(borrower.publicKey));
+    assert.ok("active" in after.state);
+  });
+
+  it("should not accept a repayer signature from anyone but the financed program", async () => {
+    try {
+      await program.methods
+        .repayFromProgram(loanId, new anchor.BN(LAMPORTS_PER_SOL))
+        .accountsPartial({
+          repayer: borrower.publicKey,
+          loan: loanPda,
+          protocolConfig: protocolConfigPda,
+          vault: vaultPda,
+          treasury: treasuryPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([borrower])
+        .rpc();
+      assert.fail("Should have thrown error");
+    } catch (error) {
+      assert.ok(error.toString().includes("ConstraintSeeds"));
+    }
+  });
+
+  it("should repay the loan in full and leave ownership with the borrower", async () => {
+    // More than what is owed; only the amount due is taken
+    await autoRepay.methods
+      .repay(loanId, new anchor.BN(2 * LAMPORTS_PER_SOL))
+      .accountsPartial(repayAccounts())
+      .rpc();
+
+    const loan = await program.account.loan.fetch(loanPda);
+    assert.equal(loan.principalOutstanding.toNumber(), 0);
+    assert.ok("repaidPendingTransfer" in loan.state);
+    assert.ok(loan.borrower.equals(borrower.publicKey));
+  });
+});
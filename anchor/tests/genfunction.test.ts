@@ -35,6 +35,9 @@ describe("solignition", () => {
   let depositor1RecordPda: PublicKey;
   let depositor2RecordPda: PublicKey;
 
+  // Every PDA is keyed by the pool's config
+  const poolId = new anchor.BN(0);
+
   // Constants
   const VAULT_SEED = Buffer.from("vault");
   const AUTHORITY_SEED = Buffer.from("authority");
@@ -43,6 +46,27 @@ describe("solignition", () => {
   const LOAN_SEED = Buffer.from("loan");
   const DEPOSITOR_SEED = Buffer.from("depositor");
   const PROTOCOL_CONFIG_SEED = Buffer.from("config");
+  const SENIOR_SEED = Buffer.from("senior");
+
+  // Accounts for a plain loan request: no quote, collateral or guarantee
+  const requestLoanAccounts = (borrowerKey: PublicKey, loan: PublicKey) => ({
+    borrower: borrowerKey,
+    loan,
+    protocolConfig: protocolConfigPda,
+    vault: vaultPda,
+    adminPda: adminPda,
+    deployer: deployer.publicKey,
+    quoteNonce: null,
+    instructions: null,
+    collateralConfig: null,
+    collateralMint: null,
+    borrowerCollateral: null,
+    loanCollateral: null,
+    tokenProgram: null,
+    guarantor: null,
+    guaranteeEscrow: null,
+    systemProgram: SystemProgram.programId,
+  });
 
   before(async () => {
     //test public keypair seed don't use on mainnet
@@ -85,37 +109,38 @@ describe("solignition", () => {
 
     // Derive PDAs
     [protocolConfigPda] = PublicKey.findProgramAddressSync(
-      [PROTOCOL_CONFIG_SEED],
+      [PROTOCOL_CONFIG_SEED, poolId.toArrayLike(Buffer, "le", 8)],
       program.programId
     );
+    const pool = protocolConfigPda.toBuffer();
 
     [vaultPda] = PublicKey.findProgramAddressSync(
-      [VAULT_SEED],
+      [VAULT_SEED, pool],
       program.programId
     );
 
     [authorityPda] = PublicKey.findProgramAddressSync(
-      [AUTHORITY_SEED],
+      [AUTHORITY_SEED, pool],
       program.programId
     );
 
     [adminPda] = PublicKey.findProgramAddressSync(
-      [ADMIN_SEED],
+      [ADMIN_SEED, pool],
       program.programId
     );
 
     [treasuryPda] = PublicKey.findProgramAddressSync(
-      [TREASURY_SEED],
+      [TREASURY_SEED, pool],
       program.programId
     );
 
     [depositor1RecordPda] = PublicKey.findProgramAddressSync(
-      [DEPOSITOR_SEED, depositor1.publicKey.toBuffer()],
+      [DEPOSITOR_SEED, pool, SENIOR_SEED, depositor1.publicKey.toBuffer()],
       program.programId
     );
 
     [depositor2RecordPda] = PublicKey.findProgramAddressSync(
-      [DEPOSITOR_SEED, depositor2.publicKey.toBuffer()],
+      [DEPOSITOR_SEED, pool, SENIOR_SEED, depositor2.publicKey.toBuffer()],
       program.programId
     );
   });
@@ -133,8 +158,8 @@ describe("solignition", () => {
    // });
 
       const tx = await program.methods
-        .initialize(adminFeeSplitBps, defaultInterestRateBps, defaultAdminFeeBps)
-        .accounts({
+        .initialize(poolId, adminFeeSplitBps, defaultInterestRateBps, defaultAdminFeeBps, 10000, 0)
+        .accountsPartial({
           admin: admin.publicKey,
           protocolConfig: protocolConfigPda,
           vault: vaultPda,
@@ -153,7 +178,7 @@ describe("solignition", () => {
       const config = await program.account.protocolConfig.fetch(protocolConfigPda);
       assert.ok(config.admin.equals(admin.publicKey));
       assert.ok(config.treasury.equals(treasuryPda));
-      assert.ok(config.deployer.equals(deployer.publicKey));
+      assert.ok(config.activeDeployers[0].equals(deployer.publicKey));
       assert.equal(config.adminFeeSplitBps, adminFeeSplitBps);
       assert.equal(config.defaultInterestRateBps, defaultInterestRateBps);
       assert.equal(config.defaultAdminFeeBps, defaultAdminFeeBps);
@@ -168,8 +193,8 @@ describe("solignition", () => {
     it("should fail to initialize twice", async () => {
       try {
         await program.methods
-          .initialize(5000, 500, 100)
-          .accounts({
+          .initialize(poolId, 5000, 500, 100, 10000, 0)
+          .accountsPartial({
             admin: admin.publicKey,
             protocolConfig: protocolConfigPda,
             vault: vaultPda,
@@ -196,8 +221,8 @@ describe("solignition", () => {
       const depositorBalanceBefore = await connection.getBalance(depositor1.publicKey);
 
       const tx = await program.methods
-        .deposit(depositAmount)
-        .accounts({
+        .deposit({ senior: {} }, depositAmount)
+        .accountsPartial({
           depositor: depositor1.publicKey,
           depositorRecord: depositor1RecordPda,
           protocolConfig: protocolConfigPda,
@@ -230,8 +255,8 @@ describe("solignition", () => {
       const depositAmount = new anchor.BN(5 * LAMPORTS_PER_SOL);
 
       await program.methods
-        .deposit(depositAmount)
-        .accounts({
+        .deposit({ senior: {} }, depositAmount)
+        .accountsPartial({
           depositor: depositor1.publicKey,
           depositorRecord: depositor1RecordPda,
           protocolConfig: protocolConfigPda,
@@ -252,8 +277,8 @@ describe("solignition", () => {
       const depositAmount = new anchor.BN(20 * LAMPORTS_PER_SOL);
 
       await program.methods
-        .deposit(depositAmount)
-        .accounts({
+        .deposit({ senior: {} }, depositAmount)
+        .accountsPartial({
           depositor: depositor2.publicKey,
           depositorRecord: depositor2RecordPda,
           protocolConfig: protocolConfigPda,
@@ -271,8 +296,8 @@ describe("solignition", () => {
     it("should fail to deposit zero amount", async () => {
       try {
         await program.methods
-          .deposit(new anchor.BN(0))
-          .accounts({
+          .deposit({ senior: {} }, new anchor.BN(0))
+          .accountsPartial({
             depositor: depositor1.publicKey,
             depositorRecord: depositor1RecordPda,
             protocolConfig: protocolConfigPda,
@@ -290,14 +315,15 @@ describe("solignition", () => {
 /*
   describe("withdraw", () => {
     it("should allow depositor to withdraw SOL", async () => {
+      // Shares are still worth a lamport each
       const withdrawAmount = new anchor.BN(5 * LAMPORTS_PER_SOL);
 
       const vaultBalanceBefore = await connection.getBalance(vaultPda);
       const depositorBalanceBefore = await connection.getBalance(depositor1.publicKey);
 
       const tx = await program.methods
-        .withdraw(withdrawAmount)
-        .accounts({
+        .withdraw({ senior: {} }, withdrawAmount, new anchor.BN(0))
+        .accountsPartial({
           depositor: depositor1.publicKey,
           depositorRecord: depositor1RecordPda,
           protocolConfig: protocolConfigPda,
@@ -328,8 +354,8 @@ describe("solignition", () => {
 
       try {
         await program.methods
-          .withdraw(withdrawAmount)
-          .accounts({
+          .withdraw({ senior: {} }, withdrawAmount, new anchor.BN(0))
+          .accountsPartial({
             depositor: depositor1.publicKey,
             depositorRecord: depositor1RecordPda,
             protocolConfig: protocolConfigPda,
@@ -347,8 +373,8 @@ describe("solignition", () => {
     it("should fail if wrong depositor tries to withdraw", async () => {
       try {
         await program.methods
-          .withdraw(new anchor.BN(1 * LAMPORTS_PER_SOL))
-          .accounts({
+          .withdraw({ senior: {} }, new anchor.BN(1 * LAMPORTS_PER_SOL), new anchor.BN(0))
+          .accountsPartial({
             depositor: depositor2.publicKey,
             depositorRecord: depositor1RecordPda,
             protocolConfig: protocolConfigPda,
@@ -379,7 +405,7 @@ describe("solignition", () => {
       const config0 = await program.account.protocolConfig.fetch(protocolConfigPda);
       //loanId = 1;
       [loanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8),borrower.publicKey.toBuffer()],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
     });
@@ -389,7 +415,7 @@ describe("solignition", () => {
       console.log("Current loan counter:", config0.loanCounter.toString());
       //loanId = 1;
       [loanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8),borrower.publicKey.toBuffer() ],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
     
@@ -406,18 +432,12 @@ describe("solignition", () => {
           principal,
           duration,
           interestRateBps,
-          adminFeeBps
+          adminFeeBps,
+          null,
+          [],
+          new anchor.BN(0)
         )
-        .accounts({
-          borrower: borrower.publicKey,
-          loan: loanPda,
-          protocolConfig: protocolConfigPda,
-          vault: vaultPda,
-          //authorityPda: authorityPda,
-          adminPda: adminPda,
-          deployer: deployer.publicKey,
-          //systemProgram: SystemProgram.programId,
-        })
+        .accountsPartial(requestLoanAccounts(borrower.publicKey, loanPda))
         .signers([borrower])
         .rpc();
 
@@ -448,7 +468,7 @@ describe("solignition", () => {
       setTimeout( async () => {
         const config0 = await program.account.protocolConfig.fetch(protocolConfigPda);
       [loanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8), borrower.publicKey.toBuffer()],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
       
@@ -465,18 +485,12 @@ describe("solignition", () => {
           principal,
           duration,
           interestRateBps,
-          adminFeeBps
+          adminFeeBps,
+          null,
+          [],
+          new anchor.BN(0)
         )
-        .accounts({
-          borrower: depositor1.publicKey,
-          loan: loanPda,
-          protocolConfig: protocolConfigPda,
-          vault: vaultPda,
-          //authorityPda: authorityPda,
-          adminPda: adminPda,
-          deployer: deployer.publicKey,
-          //systemProgram: SystemProgram.programId,
-        })
+        .accountsPartial(requestLoanAccounts(depositor1.publicKey, loanPda))
         .signers([depositor1])
         .rpc();
 
@@ -509,7 +523,7 @@ describe("solignition", () => {
       const loanId2 = 2;
       const config0 = await program.account.protocolConfig.fetch(protocolConfigPda);
       const [loanPda2] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
 
@@ -519,18 +533,12 @@ describe("solignition", () => {
             new anchor.BN(0),
             new anchor.BN(30 * 24 * 60 * 60),
             500,
-            100
+            100,
+            null,
+            [],
+            new anchor.BN(0)
           )
-          .accounts({
-            borrower: borrower.publicKey,
-            loan: loanPda2,
-            protocolConfig: protocolConfigPda,
-            vault: vaultPda,
-            authorityPda: authorityPda,
-            adminPda: adminPda,
-            deployerPda: deployer.publicKey,
-            systemProgram: SystemProgram.programId,
-          })
+          .accountsPartial(requestLoanAccounts(borrower.publicKey, loanPda2))
           .signers([borrower])
           .rpc();
         assert.fail("Should have thrown error");
@@ -543,7 +551,7 @@ describe("solignition", () => {
       const loanId3 = 3;
       const config0 = await program.account.protocolConfig.fetch(protocolConfigPda);
       const [loanPda3] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(config0.loanCounter).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
 
@@ -553,18 +561,12 @@ describe("solignition", () => {
             new anchor.BN(100 * LAMPORTS_PER_SOL), // More than available
             new anchor.BN(30 * 24 * 60 * 60),
             500,
-            100
+            100,
+            null,
+            [],
+            new anchor.BN(0)
           )
-          .accounts({
-            borrower: borrower.publicKey,
-            loan: loanPda3,
-            protocolConfig: protocolConfigPda,
-            vault: vaultPda,
-            authorityPda: authorityPda,
-            adminPda: adminPda,
-            deployerPda: deployer.publicKey,
-            systemProgram: SystemProgram.programId,
-          })
+          .accountsPartial(requestLoanAccounts(borrower.publicKey, loanPda3))
           .signers([borrower])
           .rpc();
         assert.fail("Should have thrown error");
@@ -582,7 +584,7 @@ describe("solignition", () => {
     before(() => {
       loanId = 1;
       [loanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(loanId).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(loanId).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
       programPubkey = Keypair.generate().publicKey;
@@ -591,7 +593,7 @@ describe("solignition", () => {
     it("should allow admin to set deployed program pubkey", async () => {
       const tx = await program.methods
         .setDeployedProgram(new anchor.BN(loanId), programPubkey)
-        .accounts({
+        .accountsPartial({
           admin: admin.publicKey,
           protocolConfig: protocolConfigPda,
           loan: loanPda,
@@ -610,36 +612,29 @@ describe("solignition", () => {
       const loanId4 = 4;
       const principal = new anchor.BN(2 * LAMPORTS_PER_SOL);
       const [loanPda4] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(loanId4).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(loanId4).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
 
       // First create a new loan
       await program.methods
         .requestLoan(
-          new anchor.BN(loanId4),
           principal,
           new anchor.BN(30 * 24 * 60 * 60),
           500,
-          100
+          100,
+          null,
+          [],
+          new anchor.BN(0)
         )
-        .accounts({
-          borrower: borrower.publicKey,
-          loan: loanPda4,
-          protocolConfig: protocolConfigPda,
-          vault: vaultPda,
-          authorityPda: authorityPda,
-          adminPda: adminPda,
-          deployerPda: deployer.publicKey,
-          systemProgram: SystemProgram.programId,
-        })
+        .accountsPartial(requestLoanAccounts(borrower.publicKey, loanPda4))
         .signers([borrower])
         .rpc();
 
       try {
         await program.methods
           .setDeployedProgram(new anchor.BN(loanId4), Keypair.generate().publicKey)
-          .accounts({
+          .accountsPartial({
             admin: depositor1.publicKey,
             protocolConfig: protocolConfigPda,
             loan: loanPda4,
@@ -656,16 +651,13 @@ describe("solignition", () => {
   describe("repay_loan", () => {
     let loanId: number;
     let loanPda: PublicKey;
-    let programDataAccount: PublicKey;
 
     before(() => {
       loanId = 1;
       [loanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(loanId).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(loanId).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
-      // Mock program data account
-      programDataAccount = Keypair.generate().publicKey;
     });
 
     it("should allow borrower to repay loan", async () => {
@@ -673,14 +665,17 @@ describe("solignition", () => {
       const loanBefore = await program.account.loan.fetch(loanPda);
 
       const tx = await program.methods
-        .repayLoan()
-        .accounts({
+        .repayLoan(new anchor.BN(loanId), new anchor.BN(10 * LAMPORTS_PER_SOL))
+        .accountsPartial({
           borrower: borrower.publicKey,
           loan: loanPda,
           protocolConfig: protocolConfigPda,
           vault: vaultPda,
-          authorityPda: authorityPda,
-          programData: programDataAccount,
+          treasury: treasuryPda,
+          positionProceeds: null,
+          loanCollateral: null,
+          borrowerCollateral: null,
+          tokenProgram: null,
           systemProgram: SystemProgram.programId,
         })
         .signers([borrower])
@@ -704,14 +699,17 @@ describe("solignition", () => {
     it("should fail to repay already repaid loan", async () => {
       try {
         await program.methods
-          .repayLoan()
-          .accounts({
+          .repayLoan(new anchor.BN(loanId), new anchor.BN(10 * LAMPORTS_PER_SOL))
+          .accountsPartial({
             borrower: borrower.publicKey,
             loan: loanPda,
             protocolConfig: protocolConfigPda,
             vault: vaultPda,
-            authorityPda: authorityPda,
-            programData: programDataAccount,
+            treasury: treasuryPda,
+            positionProceeds: null,
+            loanCollateral: null,
+            borrowerCollateral: null,
+            tokenProgram: null,
             systemProgram: SystemProgram.programId,
           })
           .signers([borrower])
@@ -727,7 +725,7 @@ describe("solignition", () => {
     it("should allow admin to pause protocol", async () => {
       const tx = await program.methods
         .setPaused(true)
-        .accounts({
+        .accountsPartial({
           admin: admin.publicKey,
           protocolConfig: protocolConfigPda,
         })
@@ -743,8 +741,8 @@ describe("solignition", () => {
     it("should fail to deposit when paused", async () => {
       try {
         await program.methods
-          .deposit(new anchor.BN(1 * LAMPORTS_PER_SOL))
-          .accounts({
+          .deposit({ senior: {} }, new anchor.BN(1 * LAMPORTS_PER_SOL))
+          .accountsPartial({
             depositor: depositor1.publicKey,
             depositorRecord: depositor1RecordPda,
             protocolConfig: protocolConfigPda,
@@ -762,7 +760,7 @@ describe("solignition", () => {
     it("should allow admin to unpause protocol", async () => {
       await program.methods
         .setPaused(false)
-        .accounts({
+        .accountsPartial({
           admin: admin.publicKey,
           protocolConfig: protocolConfigPda,
         })
@@ -777,7 +775,7 @@ describe("solignition", () => {
       try {
         await program.methods
           .setPaused(true)
-          .accounts({
+          .accountsPartial({
             admin: depositor1.publicKey,
             protocolConfig: protocolConfigPda,
           })
@@ -797,29 +795,22 @@ describe("solignition", () => {
     before(async () => {
       expiredLoanId = 5;
       [expiredLoanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(expiredLoanId).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(expiredLoanId).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
 
       // Create a loan with very short duration for testing
       await program.methods
         .requestLoan(
-          new anchor.BN(expiredLoanId),
           new anchor.BN(1 * LAMPORTS_PER_SOL),
           new anchor.BN(5), // 1 second duration
           500,
-          100
+          100,
+          null,
+          [],
+          new anchor.BN(0)
         )
-        .accounts({
-          borrower: borrower.publicKey,
-          loan: expiredLoanPda,
-          protocolConfig: protocolConfigPda,
-          vault: vaultPda,
-          authorityPda: authorityPda,
-          adminPda: adminPda,
-          deployerPda: deployer.publicKey,
-          systemProgram: SystemProgram.programId,
-        })
+        .accountsPartial(requestLoanAccounts(borrower.publicKey, expiredLoanPda))
         .signers([borrower])
         .rpc();
 
@@ -832,12 +823,15 @@ describe("solignition", () => {
 
       const tx = await program.methods
         .recoverLoan()
-        .accounts({
-          admin: admin.publicKey,
+        .accountsPartial({
+          keeper: admin.publicKey,
           protocolConfig: protocolConfigPda,
           loan: expiredLoanPda,
           adminPda: adminPda,
           treasury: treasuryPda,
+          vault: vaultPda,
+          guaranteeEscrow: null,
+          guarantor: null,
           systemProgram: SystemProgram.programId,
         })
         .signers([admin])
@@ -861,41 +855,37 @@ describe("solignition", () => {
     it("should fail to recover non-expired loan", async () => {
       const loanId6 = 6;
       const [loanPda6] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(loanId6).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(loanId6).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
 
       // Create a loan with long duration
       await program.methods
         .requestLoan(
-          new anchor.BN(loanId6),
           new anchor.BN(1 * LAMPORTS_PER_SOL),
           new anchor.BN(365 * 24 * 60 * 60), // 1 year
           500,
-          100
+          100,
+          null,
+          [],
+          new anchor.BN(0)
         )
-        .accounts({
-          borrower: borrower.publicKey,
-          loan: loanPda6,
-          protocolConfig: protocolConfigPda,
-          vault: vaultPda,
-          authorityPda: authorityPda,
-          adminPda: adminPda,
-          deployerPda: deployer.publicKey,
-          systemProgram: SystemProgram.programId,
-        })
+        .accountsPartial(requestLoanAccounts(borrower.publicKey, loanPda6))
         .signers([borrower])
         .rpc();
 
       try {
         await program.methods
           .recoverLoan()
-          .accounts({
-            admin: admin.publicKey,
+          .accountsPartial({
+            keeper: admin.publicKey,
             protocolConfig: protocolConfigPda,
             loan: loanPda6,
             adminPda: adminPda,
             treasury: treasuryPda,
+            vault: vaultPda,
+            guaranteeEscrow: null,
+            guarantor: null,
             systemProgram: SystemProgram.programId,
           })
           .signers([admin])
@@ -916,7 +906,7 @@ describe("solignition", () => {
     before(() => {
       recoveredLoanId = 5; // The expired loan we recovered earlier
       [recoveredLoanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(recoveredLoanId).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(recoveredLoanId).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
     });
@@ -927,12 +917,13 @@ describe("solignition", () => {
 
       const tx = await program.methods
         .returnReclaimedSol(reclaimAmount)
-        .accounts({
+        .accountsPartial({
           caller: admin.publicKey,
           protocolConfig: protocolConfigPda,
           loan: recoveredLoanPda,
           vault: vaultPda,
-          deployerPda: deployer.publicKey,
+          deployer: deployer.publicKey,
+          positionProceeds: null,
           systemProgram: SystemProgram.programId,
         })
         .signers([admin])
@@ -949,19 +940,20 @@ describe("solignition", () => {
     it("should fail if loan not recovered", async () => {
       const loanId4 = 4;
       const [loanPda4] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(loanId4).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(loanId4).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
 
       try {
         await program.methods
           .returnReclaimedSol(new anchor.BN(1 * LAMPORTS_PER_SOL))
-          .accounts({
+          .accountsPartial({
             caller: admin.publicKey,
             protocolConfig: protocolConfigPda,
             loan: loanPda4,
             vault: vaultPda,
-            deployerPda: deployer.publicKey,
+            deployer: deployer.publicKey,
+            positionProceeds: null,
             systemProgram: SystemProgram.programId,
           })
           .signers([admin])
@@ -984,10 +976,10 @@ describe("solignition", () => {
           newAdminFeeSplit,
           newInterestRate,
           newAdminFee,
-          null,
-          null
+          null, null, null, null, null, null, null, null,
+          null, null, null, null, null, null, null, null
         )
-        .accounts({
+        .accountsPartial({
           admin: admin.publicKey,
           protocolConfig: protocolConfigPda,
         })
@@ -1002,28 +994,12 @@ describe("solignition", () => {
       assert.equal(config.defaultInterestRateBps, newInterestRate);
       assert.equal(config.defaultAdminFeeBps, newAdminFee);
     });
-/*
-    it("should allow admin to update deployer address", async () => {
-      const newDeployer = Keypair.generate().publicKey;
-
-      await program.methods
-        .updateConfig(null, null, null, newDeployer, null)
-        .accounts({
-          admin: admin.publicKey,
-          protocolConfig: protocolConfigPda,
-        })
-        .signers([admin])
-        .rpc();
 
-      const config = await program.account.protocolConfig.fetch(protocolConfigPda);
-      assert.ok(config.deployer.equals(newDeployer));
-    });
-*/
     it("should fail if non-admin tries to update config", async () => {
       try {
         await program.methods
-          .updateConfig(5000, null, null, null, null)
-          .accounts({
+          .updateConfig(5000, null, null, null, null, null, null, null, null, null, null, null, null, null, null, null, null, null, null)
+          .accountsPartial({
             admin: depositor1.publicKey,
             protocolConfig: protocolConfigPda,
           })
@@ -1038,8 +1014,8 @@ describe("solignition", () => {
     it("should fail with invalid parameters", async () => {
       try {
         await program.methods
-          .updateConfig(20000, null, null, null, null) // > 10000 bps
-          .accounts({
+          .updateConfig(20000, null, null, null, null, null, null, null, null, null, null, null, null, null, null, null, null, null, null) // > 10000 bps
+          .accountsPartial({
             admin: admin.publicKey,
             protocolConfig: protocolConfigPda,
           })
@@ -1051,19 +1027,53 @@ describe("solignition", () => {
       }
     });
   });
+
+  describe("set_deployer", () => {
+    it("should add a registered deployer to the rotation", async () => {
+      const newDeployer = Keypair.generate().publicKey;
+
+      await program.methods
+        .setDeployer(true)
+        .accountsPartial({
+          admin: admin.publicKey,
+          protocolConfig: protocolConfigPda,
+          deployer: newDeployer,
+        })
+        .signers([admin])
+        .rpc();
+
+      const config = await program.account.protocolConfig.fetch(protocolConfigPda);
+      assert.ok(config.activeDeployers.some((d) => d.equals(newDeployer)));
+
+      // Taking it out again leaves the original deployer assigned
+      await program.methods
+        .setDeployer(false)
+        .accountsPartial({
+          admin: admin.publicKey,
+          protocolConfig: protocolConfigPda,
+          deployer: newDeployer,
+        })
+        .signers([admin])
+        .rpc();
+
+      const after = await program.account.protocolConfig.fetch(protocolConfigPda);
+      assert.equal(after.activeDeployers.length, 1);
+      assert.ok(after.activeDeployers[0].equals(deployer.publicKey));
+    });
+  });
 /* 
   describe("integration tests", () => {
     it("should handle full loan lifecycle", async () => {
       const integrationLoanId = 100;
       const [integrationLoanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, new anchor.BN(integrationLoanId).toArrayLike(Buffer, "le", 8)],
+        [LOAN_SEED, protocolConfigPda.toBuffer(), new anchor.BN(integrationLoanId).toArrayLike(Buffer, "le", 8)],
         program.programId
       );
 
       // 1. Deposit
       await program.methods
-        .deposit(new anchor.BN(10 * LAMPORTS_PER_SOL))
-        .accounts({
+        .deposit({ senior: {} }, new anchor.BN(10 * LAMPORTS_PER_SOL))
+        .accountsPartial({
           depositor: depositor1.publicKey,
           depositorRecord: depositor1RecordPda,
           protocolConfig: protocolConfigPda,
@@ -1076,22 +1086,15 @@ describe("solignition", () => {
       // 2. Request loan
       await program.methods
         .requestLoan(
-          new anchor.BN(integrationLoanId),
           new anchor.BN(3 * LAMPORTS_PER_SOL),
           new anchor.BN(1), // 1 second for quick test
           500,
-          100
+          100,
+          null,
+          [],
+          new anchor.BN(0)
         )
-        .accounts({
-          borrower: borrower.publicKey,
-          loan: integrationLoanPda,
-          protocolConfig: protocolConfigPda,
-          vault: vaultPda,
-          authorityPda: authorityPda,
-          adminPda: adminPda,
-          deployerPda: deployer.publicKey,
-          systemProgram: SystemProgram.programId,
-        })
+        .accountsPartial(requestLoanAccounts(borrower.publicKey, integrationLoanPda))
         .signers([borrower])
         .rpc();
 
@@ -1099,7 +1102,7 @@ describe("solignition", () => {
       const programPubkey = Keypair.generate().publicKey;
       await program.methods
         .setDeployedProgram(new anchor.BN(integrationLoanId), programPubkey)
-        .accounts({
+        .accountsPartial({
           admin: admin.publicKey,
           protocolConfig: protocolConfigPda,
           loan: integrationLoanPda,
@@ -1113,12 +1116,15 @@ describe("solignition", () => {
       // 5. Recover loan
       await program.methods
         .recoverLoan()
-        .accounts({
-          admin: admin.publicKey,
+        .accountsPartial({
+          keeper: admin.publicKey,
           protocolConfig: protocolConfigPda,
           loan: integrationLoanPda,
           adminPda: adminPda,
           treasury: treasuryPda,
+          vault: vaultPda,
+          guaranteeEscrow: null,
+          guarantor: null,
           systemProgram: SystemProgram.programId,
         })
         .signers([admin])
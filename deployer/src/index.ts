@@ -51,6 +51,7 @@ interface DeployerConfig {
   cluster: 'devnet' | 'testnet' | 'mainnet-beta' | 'localnet';
   graphqlEndpoint: string;
   solanaCliPath?: string;
+  poolId: number;
 }
 
 const config: DeployerConfig = {
@@ -70,6 +71,7 @@ const config: DeployerConfig = {
   cluster: (process.env.CLUSTER as any) || 'localnet',
   graphqlEndpoint: process.env.GRAPHQL_ENDPOINT || 'http://127.0.0.1:18488/subgraphs',
   solanaCliPath: process.env.SOLANA_CLI_PATH || 'solana',
+  poolId: parseInt(process.env.POOL_ID || '0'),
 };
 
 // ============ Constants ============
@@ -77,6 +79,42 @@ const VAULT_SEED = Buffer.from('vault');
 const AUTHORITY_SEED = Buffer.from('authority');
 const PROTOCOL_CONFIG_SEED = Buffer.from('config');
 const LOAN_SEED = Buffer.from('loan');
+const ADMIN_SEED = Buffer.from('admin');
+const DEPLOYER_SEED = Buffer.from('deployer');
+const GUARANTEE_SEED = Buffer.from('guarantee');
+const PROCEEDS_SEED = Buffer.from('proceeds');
+const BPF_UPGRADEABLE_LOADER = new PublicKey('BPFLoaderUpgradeab1e11111111111111111111111');
+
+// ============ PDAs ============
+// Everything except the config itself is seeded by the config of the pool we deploy for
+function findConfigPda(): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [PROTOCOL_CONFIG_SEED, new anchor.BN(config.poolId).toArrayLike(Buffer, 'le', 8)],
+    config.programId
+  )[0];
+}
+
+function findPoolPda(seed: Buffer): PublicKey {
+  return PublicKey.findProgramAddressSync([seed, findConfigPda().toBuffer()], config.programId)[0];
+}
+
+function findLoanPda(loanId: anchor.BN): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [LOAN_SEED, findConfigPda().toBuffer(), loanId.toArrayLike(Buffer, 'le', 8)],
+    config.programId
+  )[0];
+}
+
+function findDeployerRecordPda(deployer: PublicKey): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [DEPLOYER_SEED, findConfigPda().toBuffer(), deployer.toBuffer()],
+    config.programId
+  )[0];
+}
+
+function findProgramDataPda(programId: PublicKey): PublicKey {
+  return PublicKey.findProgramAddressSync([programId.toBuffer()], BPF_UPGRADEABLE_LOADER)[0];
+}
 
 // ============ Types ============
 interface DeploymentRecord {
@@ -119,6 +157,9 @@ interface LoanAccount {
   interestRateBps: number;
   adminFeeBps: number;
   reclaimedAmount: anchor.BN;
+  positionSold: boolean;
+  guarantor: PublicKey | null;
+  guaranteeAmount: anchor.BN;
 }
 
 interface LoanRequestedData {
@@ -534,6 +575,38 @@ class SolanaCliDeployer {
     }
   }
 
+  async setUpgradeAuthority(programId: string, newAuthority: PublicKey): Promise<{ signature: string }> {
+    try {
+      logger.info('Handing over upgrade authority using Solana CLI', {
+        programId,
+        newAuthority: newAuthority.toBase58(),
+      });
+
+      // The new authority is a PDA and cannot sign
+      const setAuthorityCommand = `${config.solanaCliPath || 'solana'} program set-upgrade-authority ${programId} \
+        --new-upgrade-authority ${newAuthority.toBase58()} \
+        --skip-new-upgrade-authority-signer-check \
+        --keypair ${this.deployerKeypairPath} \
+        --url ${config.rpcUrl} \
+        --commitment confirmed`;
+
+      const { stdout, stderr } = await execAsync(setAuthorityCommand);
+
+      if (stderr && !stdout.includes('Authority')) {
+        throw new Error(`Set upgrade authority error: ${stderr}`);
+      }
+
+      const signatureMatch = stdout.match(/Signature: (\w+)/);
+      const signature = signatureMatch ? signatureMatch[1] : 'unknown';
+
+      logger.info('Upgrade authority handed over', { programId, signature });
+      return { signature };
+    } catch (error) {
+      logger.error('Failed to set upgrade authority', { error });
+      throw error;
+    }
+  }
+
   async closeProgram(programId: string): Promise<{ signature: string }> {
     try {
       logger.info('Closing program using Solana CLI', { programId });
@@ -1212,26 +1285,12 @@ private async callRecoverLoan(
     logger.info('Calling recoverLoan instruction', { loanId });
     
     // Get required accounts
-    const protocolConfigPubkey = PublicKey.findProgramAddressSync(
-      [PROTOCOL_CONFIG_SEED],
-      this.program.programId
-    )[0];
-    
-    const adminPdaPubkey = PublicKey.findProgramAddressSync(
-      [Buffer.from('admin')],
-      this.program.programId
-    )[0];
-    
-    const eventAuthority = PublicKey.findProgramAddressSync(
-      [AUTHORITY_SEED],
-      this.program.programId
-    )[0];
-
-    const loanIdBn = new anchor.BN(loanId);
-      const [loanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, loanIdBn.toArrayLike(Buffer, 'le', 8), loan.borrower.toBuffer()],
-        this.program.programId
-      );
+    const protocolConfigPubkey = findConfigPda();
+    const adminPdaPubkey = findPoolPda(ADMIN_SEED);
+    const loanPda = findLoanPda(new anchor.BN(loanId));
+
+    // The guarantee accounts are only needed when the loan carries a guarantee
+    const guaranteed = loan.guaranteeAmount.gtn(0);
 
     const deployerKeypairData = await fs.readFile(config.deployerKeypairPath, 'utf8');
     const deployerKeypair = Keypair.fromSecretKey(
@@ -1258,16 +1317,19 @@ private async callRecoverLoan(
     // Build and send transaction
     const tx = await this.program.methods
       .recoverLoan()
-      .accounts({
-        admin: this.deployerWallet.publicKey, // Assuming admin is the deployer wallet
+      .accountsPartial({
+        keeper: this.deployerWallet.publicKey, // Anyone may recover; we collect the keeper bounty
         protocolConfig: protocolConfigPubkey,
         loan: loanPda,
-        deployer: this.deployerWallet.publicKey,
         adminPda: adminPdaPubkey,
         treasury: treasuryPubkey,
+        deployerRecord: findDeployerRecordPda(this.deployerWallet.publicKey),
+        vault: findPoolPda(VAULT_SEED),
+        guaranteeEscrow: guaranteed
+          ? PublicKey.findProgramAddressSync([GUARANTEE_SEED, loanPda.toBuffer()], config.programId)[0]
+          : null,
+        guarantor: guaranteed ? loan.guarantor : null,
         systemProgram: SystemProgram.programId,
-        //eventAuthority: eventAuthority,
-        program: this.program.programId,
       })
       .signers([this.deployerWallet.payer])
       .rpc();
@@ -1346,40 +1408,26 @@ private async callReturnReclaimedSol(
     });
     
     // Get required accounts
-    const protocolConfigPubkey = PublicKey.findProgramAddressSync(
-      [PROTOCOL_CONFIG_SEED],
-      this.program.programId
-    )[0];
-    
-    const vaultPubkey = PublicKey.findProgramAddressSync(
-      [VAULT_SEED],
-      this.program.programId
-    )[0];
-    
-    const eventAuthority = PublicKey.findProgramAddressSync(
-      [AUTHORITY_SEED],
-      this.program.programId
-    )[0];
-    
-    // Get the deployer PDA (this should be where the reclaimed SOL is)
-    // You may need to adjust this based on your specific deployer PDA setup
-    const deployerPdaPubkey = PublicKey.findProgramAddressSync(
-      [Buffer.from('deployer'), new anchor.BN(loanId).toArrayLike(Buffer, 'le', 8)],
-      this.program.programId
-    )[0];
+    const protocolConfigPubkey = findConfigPda();
+    const vaultPubkey = findPoolPda(VAULT_SEED);
+
+    // Once the loan's position is sold the SOL goes to its proceeds escrow
+    const positionProceeds = loan.positionSold
+      ? PublicKey.findProgramAddressSync([PROCEEDS_SEED, loanPubkey.toBuffer()], config.programId)[0]
+      : null;
     
     // Build and send transaction
     const tx = await this.program.methods
       .returnReclaimedSol(new anchor.BN(amount))
-      .accounts({
+      .accountsPartial({
         caller: this.deployerWallet.publicKey,
         protocolConfig: protocolConfigPubkey,
         loan: loanPubkey,
         vault: vaultPubkey,
         deployer: this.deployerWallet.publicKey,
+        deployerRecord: findDeployerRecordPda(this.deployerWallet.publicKey),
+        positionProceeds,
         systemProgram: SystemProgram.programId,
-        //eventAuthority: eventAuthority,
-        program: this.program.programId,
       })
       .signers([this.deployerWallet.payer])
       .rpc();
@@ -1535,7 +1583,9 @@ private async callReturnReclaimedSol(
 
     let setTx = null;
     try {
-      setTx = await this.setDeployedProgram(loanId, new PublicKey(programId), new PublicKey(borrower));
+      // set_deployed_program only accepts programs the pool's authority PDA controls
+      await this.solanaDeployer.setUpgradeAuthority(programId, findPoolPda(AUTHORITY_SEED));
+      setTx = await this.setDeployedProgram(loanId, new PublicKey(programId));
       
     } catch (error) {
       logger.info('failed to set deployed program in contract', {loanId, programId, borrower });
@@ -1553,14 +1603,8 @@ private async callReturnReclaimedSol(
       borrower: borrower.toString()
     });
 
-      const [configPda] = PublicKey.findProgramAddressSync(
-        [PROTOCOL_CONFIG_SEED],
-        this.program.programId
-      );
-      const [loanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, loanIdBn.toArrayLike(Buffer, 'le', 8), borrower.toBuffer()],
-        this.program.programId
-      );
+      const configPda = findConfigPda();
+      const loanPda = findLoanPda(loanIdBn);
 
       const deployment = await this.stateManager.getDeployment(loanId.toString());
       if (!deployment) {
@@ -1575,34 +1619,29 @@ private async callReturnReclaimedSol(
       //if (!loanAccount || loanAccount.programPubkey.equals(PublicKey.default)) {
         //throw new Error(`No program deployed for loan ${loanId}`);
       //}
-      const BPF_UPGRADEABLE_LOADER = new PublicKey(
-      "BPFLoaderUpgradeab1e11111111111111111111111"
-      );
-      
-      
-      const [programData] = PublicKey.findProgramAddressSync([new PublicKey(deployment.programId).toBuffer()], BPF_UPGRADEABLE_LOADER);
+      const programData = findProgramDataPda(new PublicKey(deployment.programId));
+      const authorityPda = findPoolPda(AUTHORITY_SEED);
 
        logger.info("\n🔑 Authority Transfer Details:");
        logger.info("Deployed Program:", deployment.programId.toString());
        logger.info("Program Data:", programData.toString());
-       logger.info("Current Authority (Deployer):", this.deployerWallet.publicKey.toString());
+       logger.info("Current Authority (Pool PDA):", authorityPda.toString());
        logger.info("New Authority (Borrower):", borrower.toString());
 
       const tx = await this.program.methods
         .transferAuthorityToBorrower(loanIdBn)
         .accountsPartial({
-          deployer: this.deployerWallet.publicKey,
           protocolConfig: configPda,
           loan: loanPda,
           borrower,
+          authorityPda,
           programData,
           bpfUpgradeableLoader: BPF_UPGRADEABLE_LOADER,
           systemProgram: anchor.web3.SystemProgram.programId,
         })
-        .signers([this.deployerWallet.payer])
         .rpc();
 
-      logger.info('transfer program auth from deployer ', { loanId, borrower , tx });
+      logger.info('transfer program auth from pool authority', { loanId, borrower , tx });
       return tx;
     } catch (error) {
       logger.error('Failed to transfer program auth from deployer', { loanId, error });
@@ -1610,24 +1649,21 @@ private async callReturnReclaimedSol(
     }
   }
 
-  private async setDeployedProgram(loanId: string, programPubkey: PublicKey, borrower: PublicKey): Promise<string> {
+  private async setDeployedProgram(loanId: string, programPubkey: PublicKey): Promise<string> {
     try {
       const loanIdBn = new anchor.BN(loanId);
-      const [configPda] = PublicKey.findProgramAddressSync(
-        [PROTOCOL_CONFIG_SEED],
-        this.program.programId
-      );
-      const [loanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, loanIdBn.toArrayLike(Buffer, 'le', 8), borrower.toBuffer()],
-        this.program.programId
-      );
+      const configPda = findConfigPda();
+      const loanPda = findLoanPda(loanIdBn);
 
       const tx = await this.program.methods
         .setDeployedProgram(loanIdBn, programPubkey)
-        .accounts({
+        .accountsPartial({
           admin: this.deployerWallet.publicKey,
           protocolConfig: configPda,
           loan: loanPda,
+          deployerRecord: findDeployerRecordPda(this.deployerWallet.publicKey),
+          authorityPda: findPoolPda(AUTHORITY_SEED),
+          programData: findProgramDataPda(programPubkey),
         })
         .signers([this.deployerWallet.payer])
         .rpc();
@@ -2221,11 +2257,7 @@ this.app.delete('/uploads/:fileId', async (req, res) => {
       const logs = transaction.meta?.logMessages || [];
 
       // Step 2: Derive the loan PDA
-      const loanIdBn = new anchor.BN(loanId);
-      const [loanPda] = PublicKey.findProgramAddressSync(
-        [LOAN_SEED, loanIdBn.toArrayLike(Buffer, 'le', 8),new PublicKey(borrower).toBuffer()],
-        config.programId
-      );
+      const loanPda = findLoanPda(new anchor.BN(loanId));
       
       let loanAccount = null;
 
@@ -2258,10 +2290,7 @@ this.app.delete('/uploads/:fileId', async (req, res) => {
       // If not in logs, get it from protocol config account
       if (!loanId) {
         logger.info('Loan ID not found in logs, querying protocol config');
-        const [configPda] = PublicKey.findProgramAddressSync(
-          [PROTOCOL_CONFIG_SEED],
-          config.programId
-        );
+        const configPda = findConfigPda();
         
         const configAccount = await connection.getAccountInfo(configPda);
         if (configAccount && configAccount.data.length >= 16) {
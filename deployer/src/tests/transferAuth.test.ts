@@ -45,16 +45,19 @@ describe("transferAuthorityToBorrower - Existing Setup", () => {
   
   // PDAs
   let configPDA: PublicKey;
+  let authorityPDA: PublicKey;
   
   // Seeds
   const PROTOCOL_CONFIG_SEED = Buffer.from("config");
   const LOAN_SEED = Buffer.from("loan");
+  const AUTHORITY_SEED = Buffer.from("authority");
   
   // Keypair paths
   const DEPLOYER_KEYPAIR_PATH = "../../../projects/testkeys/key.json";
   const ADMIN_KEYPAIR_PATH = "../../../projects/testkeys/key.json";
   
   // TEST PARAMETERS - UPDATE THESE
+  const POOL_ID = 0; // Change to the pool the loan was taken from
   const LOAN_ID = 0; // Change to your loan ID
   const BORROWER_PUBKEY = "6c9oJNMkS6JSmoKR1TWrYqjY1NQmYNi5vJivorYgu3CL"; // Change to your borrower
 
@@ -74,9 +77,13 @@ describe("transferAuthorityToBorrower - Existing Setup", () => {
     walletKeypair = deployer;
     wallet.publicKey = deployer.publicKey;
 
-    // Derive config PDA
+    // Derive the pool's config PDA and the authority PDA holding the program
     [configPDA] = PublicKey.findProgramAddressSync(
-      [PROTOCOL_CONFIG_SEED],
+      [PROTOCOL_CONFIG_SEED, new anchor.BN(POOL_ID).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    [authorityPDA] = PublicKey.findProgramAddressSync(
+      [AUTHORITY_SEED, configPDA.toBuffer()],
       program.programId
     );
 
@@ -86,11 +93,12 @@ describe("transferAuthorityToBorrower - Existing Setup", () => {
     console.log("Deployer:", deployer.publicKey.toString());
     console.log("Admin:", admin.publicKey.toString());
     console.log("Config PDA:", configPDA.toString());
+    console.log("Authority PDA:", authorityPDA.toString());
     console.log("Loan ID:", LOAN_ID);
     console.log("Borrower:", BORROWER_PUBKEY);
   });
 
-  it("Should transfer authority from deployer to borrower", async () => {
+  it("Should transfer authority from the authority PDA to borrower", async () => {
     const loanId = new anchor.BN(LOAN_ID);
     const borrowerPubkey = new PublicKey(BORROWER_PUBKEY);
 
@@ -98,8 +106,8 @@ describe("transferAuthorityToBorrower - Existing Setup", () => {
     const [loanPDA] = PublicKey.findProgramAddressSync(
       [
         LOAN_SEED,
+        configPDA.toBuffer(),
         loanId.toArrayLike(Buffer, "le", 8),
-        borrowerPubkey.toBuffer(),
       ],
       program.programId
     );
@@ -143,7 +151,7 @@ describe("transferAuthorityToBorrower - Existing Setup", () => {
     console.log("\n🔑 Authority Transfer Details:");
     console.log("Deployed Program:", loan.programPubkey.toString());
     console.log("Program Data:", programDataAddress.toString());
-    console.log("Current Authority (Deployer):", deployer.publicKey.toString());
+    console.log("Current Authority (Pool PDA):", authorityPDA.toString());
     console.log("New Authority (Borrower):", borrowerPubkey.toString());
 
     // Call transferAuthorityToBorrower
@@ -152,15 +160,15 @@ describe("transferAuthorityToBorrower - Existing Setup", () => {
     const tx = await program.methods
       .transferAuthorityToBorrower(loanId)
       .accountsPartial({
-        deployer: deployer.publicKey,
         protocolConfig: configPDA,
         loan: loanPDA,
         borrower: borrowerPubkey,
+        authorityPda: authorityPDA,
         programData: programDataAddress,
         bpfUpgradeableLoader: BPF_UPGRADEABLE_LOADER,
         systemProgram: SystemProgram.programId,
       })
-      .signers([deployer])
+      .signers([deployer]) // Only pays the fee; the wallet wrapper above doesn't sign
       .rpc();
 
     console.log("\n✅ Authority transferred successfully!");
@@ -185,8 +193,8 @@ describe("transferAuthorityToBorrower - Existing Setup", () => {
     const [loanPDA] = PublicKey.findProgramAddressSync(
       [
         LOAN_SEED,
+        configPDA.toBuffer(),
         loanId.toArrayLike(Buffer, "le", 8),
-        borrowerPubkey.toBuffer(),
       ],
       program.programId
     );